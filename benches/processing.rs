@@ -0,0 +1,154 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::env;
+use std::io;
+use toy_transaction::generator::{generate, GeneratorConfig};
+use toy_transaction::transaction_engine::{
+    TransactionInput, TransactionProcessor, TransactionType,
+};
+use toy_transaction::{csv_reader_builder, process_transaction_file, Options};
+
+/// Row counts for the end-to-end runs, 10M rows is only run when BENCH_10M is set
+fn row_counts() -> Vec<u64> {
+    let mut row_counts = vec![10_000, 100_000, 1_000_000];
+    if env::var_os("BENCH_10M").is_some() {
+        row_counts.push(10_000_000);
+    }
+    row_counts
+}
+
+fn generated_file(rows: u64) -> Vec<u8> {
+    let config = GeneratorConfig {
+        rows,
+        clients: 1_000,
+        overdraw_rate: 0.0,
+        ..GeneratorConfig::default()
+    };
+    let mut transactions = Vec::new();
+    generate(&config, &mut transactions, io::sink()).unwrap();
+    transactions
+}
+
+fn csv_reader(input: &[u8]) -> csv::Reader<&[u8]> {
+    csv_reader_builder().from_reader(input)
+}
+
+fn transaction(
+    transaction_type: TransactionType,
+    tx: u32,
+    amount: Option<f64>,
+) -> TransactionInput {
+    TransactionInput::new(transaction_type, 1, tx, amount)
+}
+
+fn parsing(c: &mut Criterion) {
+    let input = generated_file(100_000);
+    let mut group = c.benchmark_group("parsing");
+    group.throughput(Throughput::Elements(100_000));
+    group.bench_function("deserialize_100k", |b| {
+        b.iter(|| {
+            csv_reader(&input)
+                .deserialize::<TransactionInput>()
+                .map(Result::unwrap)
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn apply_transactions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_transactions");
+    for count in [1_000u32, 10_000, 100_000].iter() {
+        let transactions: Vec<TransactionInput> = (0..*count)
+            .map(|tx| {
+                let transaction_type = if tx % 3 == 2 {
+                    TransactionType::Withdrawal
+                } else {
+                    TransactionType::Deposit
+                };
+                transaction(transaction_type, tx, Some(10.0))
+            })
+            .collect();
+
+        group.throughput(Throughput::Elements(u64::from(*count)));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched(
+                    || transactions.clone(),
+                    |transactions| {
+                        let mut processor = TransactionProcessor::new(1);
+                        for transaction in transactions {
+                            let _ = processor.apply(transaction);
+                        }
+                        processor
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn dispute_lookups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispute_lookups");
+    for depth in [1_000u32, 10_000, 100_000].iter() {
+        let mut processor = TransactionProcessor::new(1);
+        for tx in 0..*depth {
+            let _ = processor.apply(transaction(TransactionType::Deposit, tx, Some(10.0)));
+        }
+
+        // Dispute and resolve the oldest and newest transactions of the history
+        group.bench_with_input(
+            BenchmarkId::from_parameter(depth),
+            &processor,
+            |b, processor| {
+                b.iter_batched(
+                    || processor.clone(),
+                    |mut processor| {
+                        for tx in [0, depth - 1].iter() {
+                            let _ =
+                                processor.apply(transaction(TransactionType::Dispute, *tx, None));
+                            let _ =
+                                processor.apply(transaction(TransactionType::Resolve, *tx, None));
+                        }
+                        processor
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn end_to_end(c: &mut Criterion) {
+    let mut group = c.benchmark_group("end_to_end");
+    group.sample_size(10);
+    for rows in row_counts() {
+        let input = generated_file(rows);
+
+        group.throughput(Throughput::Elements(rows));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &input, |b, input| {
+            b.iter_batched(
+                || Options {
+                    output: Some(Box::new(io::sink())),
+                    ..Options::default()
+                },
+                |options| process_transaction_file(csv_reader(input), options).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    parsing,
+    apply_transactions,
+    dispute_lookups,
+    end_to_end
+);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+#![no_main]
+//! Feed arbitrary rows under the expected header through the row parser and apply every row
+//! that parses.
+
+use libfuzzer_sys::fuzz_target;
+use toy_transaction::csv_reader_builder;
+use toy_transaction::transaction_engine::{TransactionInput, TransactionProcessor};
+
+const HEADER: &[u8] = b"type,client,tx,amount\n";
+
+fuzz_target!(|data: &[u8]| {
+    let input = [HEADER, data].concat();
+    let mut reader = csv_reader_builder().from_reader(input.as_slice());
+    let mut processor = TransactionProcessor::new(1);
+
+    for transaction in reader.deserialize::<TransactionInput>().flatten() {
+        let _ = processor.apply(transaction);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+//! Feed arbitrary bytes through the whole pipeline, any input may be rejected but none may
+//! panic.
+
+use libfuzzer_sys::fuzz_target;
+use std::io;
+use toy_transaction::{csv_reader_builder, process_transaction_file, Options};
+
+fuzz_target!(|data: &[u8]| {
+    let reader = csv_reader_builder().from_reader(data);
+    let options = Options {
+        output: Some(Box::new(io::sink())),
+        journal: Some(Box::new(io::sink())),
+        ledger: Some(Box::new(io::sink())),
+        ..Options::default()
+    };
+    let _ = process_transaction_file(reader, options);
+});
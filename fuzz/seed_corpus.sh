@@ -0,0 +1,16 @@
+#!/bin/sh
+# Seed each fuzz target's corpus with the csv files in etc/
+set -e
+cd "$(dirname "$0")"
+
+for target in process_file parse_row; do
+    mkdir -p "corpus/$target"
+    for file in ../etc/*.csv; do
+        cp "$file" "corpus/$target/"
+    done
+done
+
+# The row parser supplies its own header
+for file in corpus/parse_row/*.csv; do
+    tail -n +2 "$file" > "$file.rows" && mv "$file.rows" "$file"
+done
@@ -0,0 +1,107 @@
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+use std::str::FromStr;
+use toy_transaction::generator::{generate, GeneratorConfig};
+use toy_transaction::init_logging;
+use tracing::{error, info};
+
+const USAGE: &str = "Usage: generate <transactions.csv> <expected.csv> [--seed <u64>] \
+[--rows <u64>] [--clients <u16>] [--deposit-weight <u32>] [--withdrawal-weight <u32>] \
+[--dispute-rate <rate>] [--close-rate <rate>] [--chargeback-rate <rate>] \
+[--overdraw-rate <rate>] [--malformed-rate <rate>] [--skew <f64>] \
+[--log-level <error|warn|info|debug|trace>] [--log-format <text|json>]";
+
+#[derive(Default)]
+struct Config<'a> {
+    files: Vec<&'a str>,
+    generator: GeneratorConfig,
+    log_level: Option<&'a str>,
+    log_format: Option<&'a str>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let config = parse_config(&args);
+    init_logging(config.log_level, config.log_format).unwrap_or_else(|_| exit_with_usage());
+    let (transaction_file, expected_file) = match config.files.as_slice() {
+        [transaction_file, expected_file] => (*transaction_file, *expected_file),
+        _ => exit_with_usage(),
+    };
+    let generator = config.generator;
+    if let Err(err) = generator.validate() {
+        error!(error = %err, "invalid generator configuration");
+        process::exit(1);
+    }
+
+    let transactions = create_file(transaction_file);
+    let expected = create_file(expected_file);
+
+    match generate(&generator, transactions, expected) {
+        Ok(()) => info!(
+            transactions = transaction_file,
+            expected = expected_file,
+            seed = generator.seed,
+            rows = generator.rows,
+            clients = generator.clients,
+            "finished"
+        ),
+        Err(err) => {
+            error!(error = %err, "an application error occurred");
+            process::exit(3)
+        }
+    };
+}
+
+fn parse_config(args: &[String]) -> Config<'_> {
+    let mut config = Config::default();
+    let generator = &mut config.generator;
+    let mut args = args.iter().skip(1).map(String::as_str);
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            config.files.push(arg);
+            continue;
+        }
+
+        let value = args.next().unwrap_or_else(|| exit_with_usage());
+        match arg {
+            "--seed" => generator.seed = parse_value(value),
+            "--rows" => generator.rows = parse_value(value),
+            "--clients" => generator.clients = parse_value(value),
+            "--deposit-weight" => generator.deposit_weight = parse_value(value),
+            "--withdrawal-weight" => generator.withdrawal_weight = parse_value(value),
+            "--dispute-rate" => generator.dispute_rate = parse_value(value),
+            "--close-rate" => generator.close_rate = parse_value(value),
+            "--chargeback-rate" => generator.chargeback_rate = parse_value(value),
+            "--overdraw-rate" => generator.overdraw_rate = parse_value(value),
+            "--malformed-rate" => generator.malformed_rate = parse_value(value),
+            "--skew" => generator.skew = parse_value(value),
+            "--log-level" => config.log_level = Some(value),
+            "--log-format" => config.log_format = Some(value),
+            _ => exit_with_usage(),
+        }
+    }
+
+    config
+}
+
+fn parse_value<T: FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_with_usage())
+}
+
+fn create_file(location: &str) -> BufWriter<File> {
+    match File::create(location) {
+        Ok(output) => BufWriter::new(output),
+        Err(err) => {
+            error!(path = location, error = %err, "failed to create output file");
+            process::exit(2)
+        }
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
@@ -0,0 +1,383 @@
+use crate::transaction_engine::{TransactionInput, TransactionType};
+use crate::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+
+/// The columns of `TransactionInput`, in the order a headerless file has them by default
+const COLUMNS: [Column; 5] = [
+    Column::Type,
+    Column::Client,
+    Column::Tx,
+    Column::Amount,
+    Column::Timestamp,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Type,
+    Client,
+    Tx,
+    Amount,
+    Timestamp,
+}
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Type => "type",
+            Column::Client => "client",
+            Column::Tx => "tx",
+            Column::Amount => "amount",
+            Column::Timestamp => "timestamp",
+        }
+    }
+
+    /// Amounts and timestamps can be left out of a file, the other columns are needed
+    fn is_required(&self) -> bool {
+        !matches!(self, Column::Amount | Column::Timestamp)
+    }
+}
+
+/// Other header names each column goes by.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnAliases {
+    #[serde(rename = "type")]
+    pub transaction_type: Vec<String>,
+    pub client: Vec<String>,
+    pub tx: Vec<String>,
+    pub amount: Vec<String>,
+    pub timestamp: Vec<String>,
+}
+
+/// Position of each column from zero, for files without a header row.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnPositions {
+    #[serde(rename = "type")]
+    pub transaction_type: Option<usize>,
+    pub client: Option<usize>,
+    pub tx: Option<usize>,
+    pub amount: Option<usize>,
+    pub timestamp: Option<usize>,
+}
+
+/// Other values each transaction type goes by.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TypeAliases {
+    pub deposit: Vec<String>,
+    pub withdrawal: Vec<String>,
+    pub dispute: Vec<String>,
+    pub resolve: Vec<String>,
+    pub chargeback: Vec<String>,
+}
+
+/// How a partner's transaction files are laid out, every setting is optional and defaults
+/// to the standard comma separated layout with a header row.
+///
+/// ```toml
+/// delimiter = ";"
+/// quote = "'"
+///
+/// [columns]
+/// type = ["txn_type"]
+/// client = ["account_id"]
+/// amount = ["amount_eur"]
+///
+/// [types]
+/// deposit = ["DEP"]
+/// withdrawal = ["WD"]
+/// ```
+///
+/// Headerless files set `has_headers = false` and give the position of each column, from
+/// zero, under `[positions]`. Without positions the columns are taken in the standard order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    /// Quotes are treated as ordinary characters when off
+    pub quoting: bool,
+    pub has_headers: bool,
+    /// Whitespace around fields is removed
+    pub trim: bool,
+    /// Header names are matched ignoring case
+    pub columns: ColumnAliases,
+    /// Used in place of the header names when given
+    pub positions: ColumnPositions,
+    /// Type values are matched ignoring case
+    pub types: TypeAliases,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: ',',
+            quote: '"',
+            quoting: true,
+            has_headers: true,
+            trim: true,
+            columns: ColumnAliases::default(),
+            positions: ColumnPositions::default(),
+            types: TypeAliases::default(),
+        }
+    }
+}
+
+/// Where each column is found in the rows of one input.
+#[derive(Debug, Clone, PartialEq)]
+struct ColumnMap {
+    indexes: [Option<usize>; 5],
+}
+
+impl Dialect {
+    pub fn from_toml(input: &str) -> Result<Self, Error> {
+        let dialect: Dialect = toml::from_str(input)?;
+
+        for (name, value) in [("delimiter", dialect.delimiter), ("quote", dialect.quote)].iter() {
+            if !value.is_ascii() {
+                return Err(format!("{} must be a single ascii character", name).into());
+            }
+        }
+        if dialect.positions.is_set() {
+            if let Some(column) = COLUMNS
+                .iter()
+                .find(|column| column.is_required() && dialect.positions.get(**column).is_none())
+            {
+                return Err(format!("positions must include the {} column", column.name()).into());
+            }
+        }
+
+        Ok(dialect)
+    }
+
+    /// Reader settings for inputs in this dialect, the header row is read as a record so it
+    /// can be mapped to the columns
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter as u8)
+            .quote(self.quote as u8)
+            .quoting(self.quoting)
+            .has_headers(false)
+            .flexible(true)
+            .trim(if self.trim {
+                csv::Trim::All
+            } else {
+                csv::Trim::None
+            });
+        builder
+    }
+
+    /// Every row of the input mapped to the standard columns and deserialized, rows keep their
+    /// position so errors report the line of the original row
+    pub fn deserialize<'a, R: io::Read + 'a>(
+        &self,
+        reader: csv::Reader<R>,
+    ) -> Box<dyn Iterator<Item = csv::Result<TransactionInput>> + 'a> {
+        let mut records = reader.into_records();
+        let header = match self.has_headers {
+            true => match records.next() {
+                Some(Ok(header)) => Some(header),
+                Some(Err(err)) => return Box::new(Some(Err(err)).into_iter()),
+                None => return Box::new(None.into_iter()),
+            },
+            false => None,
+        };
+        let column_map = match self.column_map(header.as_ref()) {
+            Ok(column_map) => column_map,
+            Err(err) => return Box::new(Some(Err(err)).into_iter()),
+        };
+
+        let types = self.type_aliases();
+        let headers = csv::StringRecord::from(
+            COLUMNS
+                .iter()
+                .map(Column::name)
+                .collect::<Vec<&'static str>>(),
+        );
+        Box::new(records.map(move |record| {
+            let record = record?;
+            let mut mapped = column_map.map(&record, &types);
+            mapped.set_position(record.position().cloned());
+            mapped.deserialize(Some(&headers))
+        }))
+    }
+
+    /// Find each column by position or else by header name, failing when a column the
+    /// transactions need is missing
+    fn column_map(&self, header: Option<&csv::StringRecord>) -> csv::Result<ColumnMap> {
+        let mut indexes = [None; 5];
+        for (index, column) in indexes.iter_mut().zip(COLUMNS.iter()) {
+            *index = match (self.positions.get(*column), header) {
+                (Some(position), _) => Some(position),
+                (None, Some(header)) => header
+                    .iter()
+                    .position(|name| self.is_header_for(*column, name)),
+                (None, None) if !self.positions.is_set() => {
+                    COLUMNS.iter().position(|other| other == column)
+                }
+                (None, None) => None,
+            };
+
+            if index.is_none() && column.is_required() {
+                let message = format!("no {} column found", column.name());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message).into());
+            }
+        }
+        Ok(ColumnMap { indexes })
+    }
+
+    fn is_header_for(&self, column: Column, name: &str) -> bool {
+        let name = name.trim();
+        name.eq_ignore_ascii_case(column.name())
+            || self
+                .columns
+                .get(column)
+                .iter()
+                .any(|alias| name.eq_ignore_ascii_case(alias.trim()))
+    }
+
+    /// Every alias in lower case along with the standard name of its type
+    fn type_aliases(&self) -> HashMap<String, &'static str> {
+        let types = &self.types;
+        [
+            (TransactionType::Deposit, &types.deposit),
+            (TransactionType::Withdrawal, &types.withdrawal),
+            (TransactionType::Dispute, &types.dispute),
+            (TransactionType::Resolve, &types.resolve),
+            (TransactionType::Chargeback, &types.chargeback),
+        ]
+        .iter()
+        .flat_map(|(transaction_type, aliases)| {
+            aliases
+                .iter()
+                .map(move |alias| (alias.trim().to_lowercase(), transaction_type.name()))
+        })
+        .collect()
+    }
+}
+
+impl ColumnAliases {
+    fn get(&self, column: Column) -> &[String] {
+        match column {
+            Column::Type => &self.transaction_type,
+            Column::Client => &self.client,
+            Column::Tx => &self.tx,
+            Column::Amount => &self.amount,
+            Column::Timestamp => &self.timestamp,
+        }
+    }
+}
+
+impl ColumnPositions {
+    fn get(&self, column: Column) -> Option<usize> {
+        match column {
+            Column::Type => self.transaction_type,
+            Column::Client => self.client,
+            Column::Tx => self.tx,
+            Column::Amount => self.amount,
+            Column::Timestamp => self.timestamp,
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        COLUMNS.iter().any(|column| self.get(*column).is_some())
+    }
+}
+
+impl ColumnMap {
+    /// The row's fields in the standard column order, missing fields are left empty
+    fn map(&self, record: &csv::StringRecord, types: &HashMap<String, &str>) -> csv::StringRecord {
+        let mut mapped = csv::StringRecord::with_capacity(record.as_slice().len(), COLUMNS.len());
+        for (index, column) in self.indexes.iter().zip(COLUMNS.iter()) {
+            let field = index.and_then(|index| record.get(index)).unwrap_or("");
+            match column {
+                Column::Type => {
+                    let alias = types.get(&field.to_lowercase()).copied();
+                    mapped.push_field(alias.unwrap_or(field));
+                }
+                _ => mapped.push_field(field),
+            }
+        }
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(dialect: &str, input: &str) -> csv::Result<Vec<TransactionInput>> {
+        let dialect = Dialect::from_toml(dialect).unwrap();
+        let reader = dialect.reader_builder().from_reader(input.as_bytes());
+        dialect.deserialize(reader).collect()
+    }
+
+    #[test]
+    fn map_header_aliases_and_type_values() {
+        let dialect = r#"
+            delimiter = ";"
+
+            [columns]
+            type = ["txn_type"]
+            client = ["account_id"]
+            amount = ["amount_eur"]
+
+            [types]
+            deposit = ["DEP"]
+            withdrawal = ["WD"]
+        "#;
+
+        let rows = read(
+            dialect,
+            "account_id; txn_type; tx; amount_eur\n1;DEP;1;10.5\n1;wd;2;2.0\n1;dispute;1;\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                TransactionInput::deposit(1, 1, 10.5),
+                TransactionInput::withdrawal(1, 2, 2.0),
+                TransactionInput::dispute(1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_headerless_files_by_position() {
+        let dialect = r#"
+            delimiter = "\t"
+            has_headers = false
+
+            [positions]
+            client = 0
+            tx = 1
+            type = 2
+            amount = 4
+        "#;
+
+        let rows = read(dialect, "1\t1\tdeposit\tignored\t3.0\n").unwrap();
+
+        assert_eq!(rows, vec![TransactionInput::deposit(1, 1, 3.0)]);
+        assert_eq!(
+            read("has_headers = false", "deposit,1,1,3.0\n").unwrap(),
+            rows
+        );
+    }
+
+    #[test]
+    fn report_missing_columns_and_bad_rows() {
+        assert!(read("", "kind,client,tx,amount\ndeposit,1,1,1.0\n").is_err());
+
+        let err = read("", "type,client,tx,amount\ndeposit,1,1,1.0\nDEP,1,2,1.0\n").unwrap_err();
+        assert_eq!(err.position().map(csv::Position::line), Some(3));
+
+        assert!(Dialect::from_toml("delimiter = \"€\"").is_err());
+        assert!(Dialect::from_toml("[positions]\ntype = 0").is_err());
+        assert!(Dialect::from_toml("[columns]\nkind = [\"type\"]").is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::Error;
+use std::collections::BTreeMap;
+use std::io;
+
+/// Amounts are generated in ten-thousandths so the expected balances are exact
+const UNITS: i64 = 10_000;
+const MAX_DEPOSIT: u64 = 10_000 * UNITS as u64;
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    pub rows: u64,
+    pub clients: u16,
+    /// Relative weight of deposits against withdrawals
+    pub deposit_weight: u32,
+    pub withdrawal_weight: u32,
+    /// Chance a row disputes one of the client's earlier transactions
+    pub dispute_rate: f64,
+    /// Chance a row resolves or charges back one of the client's open disputes
+    pub close_rate: f64,
+    /// Share of closed disputes that are charged back rather than resolved
+    pub chargeback_rate: f64,
+    /// Chance a withdrawal asks for more than the available funds and is rejected
+    pub overdraw_rate: f64,
+    /// Chance a row is malformed, a missing or unreadable amount or an unknown transaction
+    pub malformed_rate: f64,
+    /// Skew of the client distribution, 1.0 is uniform and larger values favour low client ids
+    pub skew: f64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            seed: 0,
+            rows: 10_000,
+            clients: 100,
+            deposit_weight: 6,
+            withdrawal_weight: 4,
+            dispute_rate: 0.02,
+            close_rate: 0.02,
+            chargeback_rate: 0.25,
+            overdraw_rate: 0.1,
+            malformed_rate: 0.0,
+            skew: 1.0,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        let rates = [
+            ("dispute_rate", self.dispute_rate),
+            ("close_rate", self.close_rate),
+            ("chargeback_rate", self.chargeback_rate),
+            ("overdraw_rate", self.overdraw_rate),
+            ("malformed_rate", self.malformed_rate),
+        ];
+        for (name, rate) in rates.iter() {
+            if !(0.0..=1.0).contains(rate) {
+                return Err(format!("{} must be between 0 and 1", name).into());
+            }
+        }
+        if self.dispute_rate + self.close_rate + self.malformed_rate > 1.0 {
+            return Err("dispute, close and malformed rates must not add up to more than 1".into());
+        }
+        if self.clients == 0 {
+            return Err("clients must be greater than zero".into());
+        }
+        if self.deposit_weight + self.withdrawal_weight == 0 {
+            return Err("deposit and withdrawal weights can not both be zero".into());
+        }
+        if !(self.skew >= 1.0 && self.skew.is_finite()) {
+            return Err("skew must be 1.0 or greater".into());
+        }
+        Ok(())
+    }
+}
+
+/// SplitMix64, small and stable so a seed always produces the same file.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settled {
+    tx: u32,
+    amount: i64,
+    deposit: bool,
+}
+
+#[derive(Debug, Default)]
+struct ClientModel {
+    available: i64,
+    held: i64,
+    locked: bool,
+    disputable: Vec<Settled>,
+    disputed: Vec<Settled>,
+}
+
+/// Generate a transaction file along with the balances the engine is expected to produce
+/// from it, in the same shape as the engine output.
+pub fn generate<T: io::Write, E: io::Write>(
+    config: &GeneratorConfig,
+    transactions: T,
+    expected: E,
+) -> Result<(), Error> {
+    config.validate()?;
+
+    let mut rng = Rng(config.seed);
+    let mut clients: BTreeMap<u16, ClientModel> = BTreeMap::new();
+    let mut transaction_writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(transactions);
+    let mut next_tx: u32 = 1;
+
+    transaction_writer.write_record(["type", "client", "tx", "amount"])?;
+
+    for _ in 0..config.rows {
+        let client = pick_client(&mut rng, config);
+        let model = clients.entry(client).or_default();
+        let client = client.to_string();
+        let roll = rng.next_f64();
+
+        if roll < config.malformed_rate {
+            let record = malformed_record(&mut rng, &client, next_tx);
+            transaction_writer.write_record(&record)?;
+            next_tx += 1;
+        } else if roll < config.malformed_rate + config.dispute_rate && !model.disputable.is_empty()
+        {
+            let index = rng.below(model.disputable.len() as u64) as usize;
+            let settled = model.disputable.swap_remove(index);
+            // A disputed withdrawal is provisionally credited, the money already left
+            if settled.deposit {
+                model.available -= settled.amount;
+            }
+            model.held += settled.amount;
+            model.disputed.push(settled);
+            transaction_writer.write_record(["dispute", &client, &settled.tx.to_string()])?;
+        } else if roll < config.malformed_rate + config.dispute_rate + config.close_rate
+            && !model.disputed.is_empty()
+        {
+            let index = rng.below(model.disputed.len() as u64) as usize;
+            let settled = model.disputed.swap_remove(index);
+            model.held -= settled.amount;
+            let transaction_type = if rng.next_f64() < config.chargeback_rate {
+                if !settled.deposit {
+                    model.available += settled.amount;
+                }
+                model.locked = true;
+                "chargeback"
+            } else {
+                if settled.deposit {
+                    model.available += settled.amount;
+                }
+                "resolve"
+            };
+            transaction_writer.write_record([
+                transaction_type,
+                &client,
+                &settled.tx.to_string(),
+            ])?;
+        } else {
+            let weight = config.deposit_weight + config.withdrawal_weight;
+            let deposit = rng.below(u64::from(weight)) < u64::from(config.deposit_weight);
+            let amount = if deposit {
+                (rng.below(MAX_DEPOSIT) + 1) as i64
+            } else {
+                withdrawal_amount(&mut rng, model, config.overdraw_rate)
+            };
+            let applied = deposit || amount < model.available;
+            if applied {
+                model.available += if deposit { amount } else { -amount };
+                model.disputable.push(Settled {
+                    tx: next_tx,
+                    amount,
+                    deposit,
+                });
+            }
+            transaction_writer.write_record([
+                if deposit { "deposit" } else { "withdrawal" },
+                &client,
+                &next_tx.to_string(),
+                &format_amount(amount),
+            ])?;
+            next_tx += 1;
+        }
+    }
+    transaction_writer.flush()?;
+
+    let mut expected_writer = csv::Writer::from_writer(expected);
+    expected_writer.write_record(["client", "available", "held", "total", "locked"])?;
+    for (client, model) in &clients {
+        expected_writer.write_record([
+            client.to_string(),
+            format_amount(model.available),
+            format_amount(model.held),
+            format_amount(model.available + model.held),
+            model.locked.to_string(),
+        ])?;
+    }
+    expected_writer.flush()?;
+
+    Ok(())
+}
+
+fn pick_client(rng: &mut Rng, config: &GeneratorConfig) -> u16 {
+    let position = rng.next_f64().powf(config.skew);
+    let index = (position * f64::from(config.clients)) as u16;
+    index.min(config.clients - 1) + 1
+}
+
+/// Withdraw part of the available funds, or more than is available so the withdrawal is
+/// rejected. Withdrawing exactly the available funds is avoided as the engine's floating
+/// point balance may land either side of it.
+fn withdrawal_amount(rng: &mut Rng, model: &ClientModel, overdraw_rate: f64) -> i64 {
+    if model.available > 1 && rng.next_f64() >= overdraw_rate {
+        (rng.below(model.available as u64 - 1) + 1) as i64
+    } else {
+        model.available.max(0) + (rng.below(MAX_DEPOSIT) + 1) as i64
+    }
+}
+
+fn malformed_record(rng: &mut Rng, client: &str, tx: u32) -> [String; 4] {
+    let (transaction_type, amount) = match rng.below(3) {
+        0 => ("deposit", ""),
+        1 => ("withdrawal", "not-a-number"),
+        // Transaction ids are only ever used once, so this one can not be found
+        _ => ("dispute", ""),
+    };
+    [
+        transaction_type.to_string(),
+        client.to_string(),
+        tx.to_string(),
+        amount.to_string(),
+    ]
+}
+
+fn format_amount(amount: i64) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.abs();
+    format!("{}{}.{:04}", sign, amount / UNITS, amount % UNITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_engine::{reconcile, Balance};
+    use crate::{csv_reader_builder, process_transactions, Options};
+
+    fn generate_to_strings(config: &GeneratorConfig) -> (String, String) {
+        let mut transactions = Vec::new();
+        let mut expected = Vec::new();
+        generate(config, &mut transactions, &mut expected).unwrap();
+        (
+            String::from_utf8(transactions).unwrap(),
+            String::from_utf8(expected).unwrap(),
+        )
+    }
+
+    #[test]
+    fn same_seed_generates_same_file() {
+        let config = GeneratorConfig {
+            rows: 500,
+            malformed_rate: 0.05,
+            ..GeneratorConfig::default()
+        };
+
+        assert_eq!(generate_to_strings(&config), generate_to_strings(&config));
+    }
+
+    #[test]
+    fn engine_matches_the_expected_balances() {
+        let config = GeneratorConfig {
+            seed: 31,
+            rows: 5_000,
+            clients: 25,
+            dispute_rate: 0.05,
+            close_rate: 0.05,
+            chargeback_rate: 0.5,
+            malformed_rate: 0.05,
+            skew: 2.0,
+            ..GeneratorConfig::default()
+        };
+        let (transactions, expected) = generate_to_strings(&config);
+        let expected = csv_reader_builder()
+            .from_reader(expected.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<Balance>, _>>()
+            .unwrap();
+
+        let reader = csv_reader_builder().from_reader(transactions.as_bytes());
+        let (engine, metrics) = process_transactions(reader.into(), Options::default()).unwrap();
+        let running_states = engine.finish().unwrap();
+
+        // Malformed rows are rejected, along with the withdrawals that overdraw
+        for reason in [
+            "invalid_amount",
+            "transaction_not_found",
+            "insufficient_funds",
+        ]
+        .iter()
+        {
+            assert!(
+                metrics.rejections.contains_key(reason),
+                "no {} rows",
+                reason
+            );
+        }
+        assert!(running_states.iter().any(|state| state.locked()));
+        assert!(running_states.iter().any(|state| state.held() > 0.0));
+        assert_eq!(reconcile(&running_states, &expected, 1e-9), Vec::new());
+    }
+
+    #[test]
+    fn format_amount_to_four_places() {
+        assert_eq!(format_amount(12_345), "1.2345");
+        assert_eq!(format_amount(-5), "-0.0005");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::transaction_engine::TransactionInput;
+use crate::{csv_reader_builder, Dialect, Error};
+use flate2::bufread::MultiGzDecoder;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Endings of the transaction files picked up from a directory
+const EXTENSIONS: [&str; 3] = [".csv", ".csv.gz", ".csv.zst"];
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of an input, detected from its first bytes or else its file name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn detect(name: &str, header: &[u8]) -> Self {
+        if header.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if name.ends_with(".gz") {
+            Compression::Gzip
+        } else if name.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+type Rows<'a> = Box<dyn Iterator<Item = csv::Result<TransactionInput>> + 'a>;
+
+/// Transaction csv inputs read one after another as a single continuous stream, so disputes
+/// can refer to transactions from an earlier input. Each input has its own header row and
+/// files are only opened once the inputs before them are read.
+pub struct Inputs<'a> {
+    inputs: Vec<Input<'a>>,
+    /// Layout of the files and raw inputs, the standard layout when not set
+    dialect: Option<Rc<Dialect>>,
+}
+
+enum Input<'a> {
+    Path(PathBuf),
+    Raw {
+        name: String,
+        input: Box<dyn io::Read + 'a>,
+    },
+    Reader {
+        name: String,
+        rows: Rows<'a>,
+    },
+}
+
+/// A row that could not be read, along with the input and line it is on.
+#[derive(Debug)]
+pub struct InputError {
+    /// File name of the input, `input` for a reader without a name
+    pub name: String,
+    /// Line of the row, not known when the input could not be opened
+    pub line: Option<u64>,
+    pub source: csv::Error,
+}
+
+impl<'a> Inputs<'a> {
+    pub fn new() -> Self {
+        Inputs {
+            inputs: Vec::new(),
+            dialect: None,
+        }
+    }
+
+    /// Inputs for each path in order, a directory stands for the csv files directly within it
+    /// and a pattern such as `data/2024-*.csv` for the files it matches, both in file name
+    /// order. Fails when a path does not exist or a pattern matches no files.
+    pub fn from_paths<S: AsRef<str>>(paths: &[S]) -> Result<Self, Error> {
+        let mut inputs = Inputs::new();
+        for path in paths {
+            for file in expand(path.as_ref())? {
+                inputs = inputs.with_path(file);
+            }
+        }
+        Ok(inputs)
+    }
+
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.inputs.push(Input::Path(path.into()));
+        self
+    }
+
+    /// Add an input that may be gzip or zstd compressed, it is decompressed as it is read
+    pub fn with_input<R: io::Read + 'a>(mut self, name: &str, input: R) -> Self {
+        self.inputs.push(Input::Raw {
+            name: name.to_string(),
+            input: Box::new(input),
+        });
+        self
+    }
+
+    /// Read the files and raw inputs in the dialect, csv readers are read as they are
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Some(Rc::new(dialect));
+        self
+    }
+
+    pub fn with_reader<R: io::Read + 'a>(mut self, name: &str, reader: csv::Reader<R>) -> Self {
+        self.inputs.push(Input::Reader {
+            name: name.to_string(),
+            rows: Box::new(reader.into_deserialize()),
+        });
+        self
+    }
+
+    /// Every row of every input in order, opening each file as it is reached
+    pub fn rows(self) -> impl Iterator<Item = Result<TransactionInput, InputError>> + 'a {
+        let dialect = self.dialect;
+        self.inputs.into_iter().flat_map(move |input| {
+            let (name, input) = match input {
+                Input::Reader { name, rows } => (name, Ok(rows)),
+                Input::Raw { name, input } => {
+                    let rows =
+                        decompress(&name, input).map(|input| read(dialect.as_deref(), input));
+                    (name, rows)
+                }
+                Input::Path(path) => {
+                    let name = path.display().to_string();
+                    let rows = File::open(&path)
+                        .and_then(|file| decompress(&name, file))
+                        .map(|input| read(dialect.as_deref(), input));
+                    (name, rows)
+                }
+            };
+            let rows = input.unwrap_or_else(|err| {
+                Box::new(Some(Err(csv::Error::from(err))).into_iter()) as Rows
+            });
+            rows.map(move |row| {
+                row.map_err(|source| InputError {
+                    name: name.clone(),
+                    line: source.position().map(csv::Position::line),
+                    source,
+                })
+            })
+        })
+    }
+}
+
+impl Default for Inputs<'_> {
+    fn default() -> Self {
+        Inputs::new()
+    }
+}
+
+impl<'a, R: io::Read + 'a> From<csv::Reader<R>> for Inputs<'a> {
+    fn from(reader: csv::Reader<R>) -> Self {
+        Inputs::new().with_reader("input", reader)
+    }
+}
+
+impl InputError {
+    /// The row could not be parsed, as opposed to the input failing to be read
+    pub fn is_malformed_row(&self) -> bool {
+        matches!(
+            self.source.kind(),
+            csv::ErrorKind::Deserialize { .. }
+                | csv::ErrorKind::UnequalLengths { .. }
+                | csv::ErrorKind::Utf8 { .. }
+        )
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} line {}: {}", self.name, line, self.source),
+            None => write!(f, "{}: {}", self.name, self.source),
+        }
+    }
+}
+
+impl error::Error for InputError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Wrap the input to be decompressed as it is read when it is gzip or zstd compressed,
+/// nothing is decompressed up front
+pub fn decompress<'a, R: io::Read + 'a>(
+    name: &str,
+    input: R,
+) -> io::Result<Box<dyn io::Read + 'a>> {
+    let mut input = io::BufReader::new(input);
+    let compression = Compression::detect(name, input.fill_buf()?);
+
+    Ok(match compression {
+        Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(input)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(input)?),
+    })
+}
+
+/// Deserialize the rows of the input in the dialect, or in the standard layout without one
+fn read<'a>(dialect: Option<&Dialect>, input: Box<dyn io::Read + 'a>) -> Rows<'a> {
+    match dialect {
+        Some(dialect) => dialect.deserialize(dialect.reader_builder().from_reader(input)),
+        None => Box::new(csv_reader_builder().from_reader(input).into_deserialize()),
+    }
+}
+
+/// Open a csv file that may be gzip or zstd compressed
+pub fn open_csv<P: AsRef<Path>>(path: P) -> io::Result<csv::Reader<Box<dyn io::Read>>> {
+    let path = path.as_ref();
+    let input = decompress(&path.display().to_string(), File::open(path)?)?;
+    Ok(csv_reader_builder().from_reader(input))
+}
+
+/// The files a path stands for, in file name order for directories and patterns
+fn expand(path: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut files = if Path::new(path).is_dir() {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, io::Error>>()?
+            .into_iter()
+            .filter(|file| file.is_file() && is_transaction_file(file))
+            .collect()
+    } else if Path::new(path).exists() {
+        return Ok(vec![PathBuf::from(path)]);
+    } else {
+        glob::glob(path)?
+            .collect::<Result<Vec<PathBuf>, glob::GlobError>>()?
+            .into_iter()
+            .filter(|file| file.is_file())
+            .collect::<Vec<PathBuf>>()
+    };
+
+    if files.is_empty() {
+        return Err(format!("no transaction files found for {}", path).into());
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_transaction_file(file: &Path) -> bool {
+    let name = file.to_string_lossy();
+    EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(input: &str) -> csv::Reader<&[u8]> {
+        csv_reader_builder().from_reader(input.as_bytes())
+    }
+
+    #[test]
+    fn read_inputs_as_one_stream() {
+        let inputs = Inputs::new()
+            .with_reader(
+                "day1.csv",
+                reader("type,client,tx,amount\ndeposit,1,1,1.0\n"),
+            )
+            .with_reader("day2.csv", reader("type,client,tx,amount\ndispute,1,1,\n"));
+
+        let rows: Vec<TransactionInput> = inputs.rows().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                TransactionInput::deposit(1, 1, 1.0),
+                TransactionInput::dispute(1, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn report_the_input_and_line_of_a_malformed_row() {
+        let inputs = Inputs::new()
+            .with_reader(
+                "day1.csv",
+                reader("type,client,tx,amount\ndeposit,1,1,1.0\n"),
+            )
+            .with_reader(
+                "day2.csv",
+                reader("type,client,tx,amount\ndeposit,1,2,1.0\nrefund,1,3,1.0\n"),
+            );
+
+        let err = inputs.rows().find_map(Result::err).unwrap();
+
+        assert!(err.is_malformed_row());
+        assert_eq!((err.name.as_str(), err.line), ("day2.csv", Some(3)));
+        assert!(err.to_string().starts_with("day2.csv line 3: "));
+    }
+
+    #[test]
+    fn decompress_gzip_and_zstd_inputs() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut gzip, csv.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(csv.as_bytes(), 0).unwrap();
+
+        let inputs = Inputs::new()
+            .with_input("day1.csv.gz", gzip.as_slice())
+            .with_input("day2", zstd.as_slice())
+            .with_input("day3.csv", csv.as_bytes());
+        let rows: Vec<TransactionInput> = inputs.rows().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(rows, vec![TransactionInput::deposit(1, 1, 1.0); 3]);
+    }
+}
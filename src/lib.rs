@@ -1,24 +1,509 @@
-mod transaction_engine;
+pub mod dialect;
+pub mod generator;
+pub mod inputs;
+pub mod logging;
+pub mod metrics;
+pub mod transaction_engine;
 use std::error;
 use std::io;
-use transaction_engine::{TransactionEngineProcessorState, TransactionInput};
+use std::time::Instant;
+use tracing::warn;
+use transaction_engine::{
+    AdminInput, JournalEntry, JournalType, Listeners, Outcome, Policy, Rejection, RowOrder,
+    Timestamp, TransactionInput,
+};
+
+pub use dialect::Dialect;
+pub use inputs::{decompress, open_csv, Compression, InputError, Inputs};
+pub use logging::init_logging;
+pub use metrics::Metrics;
+pub use transaction_engine::{Alert, Balance, Difference, Engine, Limits, Rules};
 
 pub type Error = Box<dyn error::Error + Sync + Send>;
 
-pub fn process_transaction_file<T: io::Read>(mut reader: csv::Reader<T>) -> Result<(), Error> {
-    let mut transaction_processor_state = TransactionEngineProcessorState::new();
+#[derive(Default)]
+pub struct Options {
+    /// Where the client balances (or reconciliation report) are written as csv, stdout when not set
+    pub output: Option<Box<dyn io::Write>>,
+    pub limits: Limits,
+    pub policy: Policy,
+    pub rules: Rules,
+    /// Authorised source of admin transactions (unlock, lock, adjust), read in order. A row with
+    /// a timestamp takes effect ahead of the first client transaction stamped at or after it,
+    /// the rest are applied after every client transaction. Once the client transactions carry
+    /// timestamps, a row without one is rejected as it can not be placed among them.
+    pub admin: Option<csv::Reader<Box<dyn io::Read>>>,
+    /// Where alerts raised by the rules are written as csv, stderr when not set
+    pub alerts: Option<Box<dyn io::Write>>,
+    /// Where every applied and rejected transaction is journaled as csv
+    pub journal: Option<Box<dyn io::Write>>,
+    /// Where the double-entry ledger postings are exported as csv, as they are made
+    pub ledger: Option<Box<dyn io::Write>>,
+    /// Where accounts left in negative available or total funds are reported as csv
+    pub negative_balances: Option<Box<dyn io::Write>>,
+    /// Where disputes still open at the end of the run are reported as csv
+    pub open_disputes: Option<Box<dyn io::Write>>,
+    /// Where the open disputes grouped by age are reported as csv
+    pub dispute_aging: Option<Box<dyn io::Write>>,
+    /// Notified of every applied and rejected transaction, dispute and account lock
+    pub listeners: Listeners,
+    /// Write the balances as they stood at this time rather than the final balances
+    pub as_of: Option<Timestamp>,
+    /// Snapshot of an earlier run's accounts to carry on from
+    pub load_snapshot: Option<Box<dyn io::Read>>,
+    /// Where a snapshot of the accounts is written at the end of the run
+    pub save_snapshot: Option<Box<dyn io::Write>>,
+}
 
-    for input in reader.deserialize() {
-        let transaction: TransactionInput = input?;
-        transaction_processor_state.add_transaction(transaction);
+/// Process the transactions and write the client balances, returning the metrics of the run.
+pub fn process_transaction_file<'a, I: Into<Inputs<'a>>>(
+    inputs: I,
+    mut options: Options,
+) -> Result<Metrics, Error> {
+    let start = Instant::now();
+    let mut csv_writer = output_writer(&mut options);
+    let as_of = options.as_of;
+    let (engine, mut metrics) = process_transactions(inputs.into(), options)?;
+    let balances_as_of = as_of.map(|as_of| engine.balances_as_of(as_of));
+    let running_states = engine.finish()?;
+    match balances_as_of {
+        Some(balances) => balances
+            .iter()
+            .try_for_each(|balance| csv_writer.serialize(balance))?,
+        None => running_states
+            .iter()
+            .try_for_each(|running_state| csv_writer.serialize(running_state))?,
     }
+    csv_writer.flush()?;
+
+    metrics.finish(&running_states, start.elapsed());
+    Ok(metrics)
+}
+
+/// Process the transactions and write a report of every difference from the expected
+/// balances, returning the metrics of the run along with the number of differences found.
+pub fn reconcile_transaction_file<'a, I: Into<Inputs<'a>>, E: io::Read>(
+    inputs: I,
+    mut expected_reader: csv::Reader<E>,
+    tolerance: f64,
+    mut options: Options,
+) -> Result<Metrics, Error> {
+    let start = Instant::now();
+    let mut csv_writer = output_writer(&mut options);
+    let expected = expected_reader
+        .deserialize()
+        .collect::<Result<Vec<Balance>, csv::Error>>()?;
+    let (engine, mut metrics) = process_transactions(inputs.into(), options)?;
+    let running_states = engine.finish()?;
+    let differences = transaction_engine::reconcile(&running_states, &expected, tolerance);
 
-    let mut csv_writer = csv::Writer::from_writer(io::stdout());
-    transaction_processor_state
-        .get_state()
+    differences
         .iter()
-        .map(|(_, transaction_processor)| transaction_processor.process_transactions())
-        .try_for_each(|t| csv_writer.serialize(t))?;
+        .try_for_each(|difference| csv_writer.serialize(difference))?;
+    csv_writer.flush()?;
+
+    metrics.differences = Some(differences.len());
+    metrics.finish(&running_states, start.elapsed());
+    Ok(metrics)
+}
+
+/// Process the transactions and write a report of every dispute left open, returning the
+/// metrics of the run.
+pub fn open_disputes_file<'a, I: Into<Inputs<'a>>>(
+    inputs: I,
+    mut options: Options,
+) -> Result<Metrics, Error> {
+    let start = Instant::now();
+    let mut csv_writer = output_writer(&mut options);
+    let (engine, mut metrics) = process_transactions(inputs.into(), options)?;
+    engine
+        .open_disputes()
+        .iter()
+        .try_for_each(|dispute| csv_writer.serialize(dispute))?;
+    csv_writer.flush()?;
+
+    let running_states = engine.finish()?;
+    metrics.finish(&running_states, start.elapsed());
+    Ok(metrics)
+}
+
+/// Reader settings for every csv input, fields are trimmed and rows may omit the amount
+pub fn csv_reader_builder() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.trim(csv::Trim::All).flexible(true);
+    builder
+}
+
+fn output_writer(options: &mut Options) -> csv::Writer<Box<dyn io::Write>> {
+    let output = options
+        .output
+        .take()
+        .unwrap_or_else(|| Box::new(io::stdout()));
+    csv::Writer::from_writer(output)
+}
+
+/// Apply every transaction and admin transaction, writing the reports as it goes, and return
+/// the engine for the final balances to be taken from
+fn process_transactions(inputs: Inputs, options: Options) -> Result<(Engine, Metrics), Error> {
+    let mut metrics = Metrics::new();
+    let strict = options.policy.input.strict;
+    let ordering = options.policy.input.ordering;
+    metrics.policy = options.policy.clone();
+    let mut engine = Engine::new()
+        .with_limits(options.limits)
+        .with_policy(options.policy)
+        .with_rules(options.rules)
+        .with_listeners(options.listeners);
+    if let Some(snapshot) = options.load_snapshot {
+        engine.load_snapshot(snapshot)?;
+    }
+    let mut alerts_writer = options.alerts.map(csv::Writer::from_writer);
+    let mut journal_writer = options.journal.map(csv::Writer::from_writer);
+    let mut ledger_writer = options.ledger.map(csv::Writer::from_writer);
+    let mut admin_rows = options
+        .admin
+        .map(|admin_reader| admin_reader.into_deserialize::<AdminInput>().peekable());
+
+    let mut skipped_rows = 0;
+    let rows = inputs.rows().filter_map(|input| match input {
+        Err(err) if !strict && err.is_malformed_row() => {
+            warn!(file = %err.name, line = err.line, error = %err.source, "skipping malformed row");
+            skipped_rows += 1;
+            None
+        }
+        input => Some(input),
+    });
+    // Sorting needs every row up front, otherwise rows are streamed
+    let rows: Box<dyn Iterator<Item = Result<TransactionInput, InputError>> + '_> =
+        if ordering == RowOrder::Sort {
+            let mut transactions = rows.collect::<Result<Vec<TransactionInput>, _>>()?;
+            transactions.sort_by_key(TransactionInput::timestamp);
+            Box::new(transactions.into_iter().map(Ok))
+        } else {
+            Box::new(rows)
+        };
+
+    // Once the client rows carry timestamps the admin rows are applied among them
+    let mut interleaved = false;
+    for input in rows {
+        let transaction = input?;
+        if let (Some(admin_rows), Some(timestamp)) = (admin_rows.as_mut(), transaction.timestamp())
+        {
+            interleaved = true;
+            // Unreadable rows and rows that can not be placed are dealt with straight away
+            let is_due = |input: &csv::Result<AdminInput>| match input {
+                Ok(admin) => admin.timestamp.is_none_or(|at| at <= timestamp),
+                Err(_) => true,
+            };
+            while let Some(input) = admin_rows.next_if(is_due) {
+                let transaction = input?;
+                apply_admin(
+                    &mut engine,
+                    &mut metrics,
+                    &transaction,
+                    check_admin_timestamp(&transaction, interleaved),
+                    alerts_writer.as_mut(),
+                    journal_writer.as_mut(),
+                    ledger_writer.as_mut(),
+                )?;
+            }
+        }
+        let (client, tx, amount) = (transaction.client(), transaction.tx(), transaction.amount());
+        let transaction_type = transaction.transaction_type().name();
+        let journal_type = JournalType::Transaction(transaction.transaction_type());
+        metrics.rows_read += 1;
+
+        let outcome = engine.apply(transaction);
+        let expired = engine.expired(client);
+        metrics.record_expired(expired);
+        if let Some(journal_writer) = journal_writer.as_mut() {
+            expired
+                .iter()
+                .try_for_each(|entry| journal_writer.serialize(entry))?;
+        }
+        write_postings(&engine, client, ledger_writer.as_mut())?;
+        record_metrics(&mut metrics, transaction_type, &outcome);
+        let outcome = outcome.map_err(|rejection| {
+            rejected_entry(&engine, client, journal_type, tx, amount, rejection)
+        });
+        record(outcome, alerts_writer.as_mut(), journal_writer.as_mut())?;
+    }
+
+    metrics.skipped_rows = skipped_rows;
+
+    for input in admin_rows.into_iter().flatten() {
+        let transaction = input?;
+        apply_admin(
+            &mut engine,
+            &mut metrics,
+            &transaction,
+            check_admin_timestamp(&transaction, interleaved),
+            alerts_writer.as_mut(),
+            journal_writer.as_mut(),
+            ledger_writer.as_mut(),
+        )?;
+    }
+
+    if let Some(ledger_writer) = ledger_writer.as_mut() {
+        ledger_writer.flush()?;
+    }
+    if let Some(negative_balances) = options.negative_balances {
+        let mut negative_balances_writer = csv::Writer::from_writer(negative_balances);
+        engine
+            .negative_balances()
+            .iter()
+            .try_for_each(|balance| negative_balances_writer.serialize(balance))?;
+        negative_balances_writer.flush()?;
+    }
+    let open_disputes = engine.open_disputes();
+    let precision = engine.policy().precision();
+    metrics.open_disputes = open_disputes.len() as u64;
+    if let Some(open_disputes_output) = options.open_disputes {
+        let mut open_disputes_writer = csv::Writer::from_writer(open_disputes_output);
+        open_disputes
+            .iter()
+            .try_for_each(|dispute| open_disputes_writer.serialize(dispute))?;
+        open_disputes_writer.flush()?;
+    }
+    if let Some(dispute_aging) = options.dispute_aging {
+        let mut dispute_aging_writer = csv::Writer::from_writer(dispute_aging);
+        transaction_engine::dispute_aging(&open_disputes, precision)
+            .iter()
+            .try_for_each(|bucket| dispute_aging_writer.serialize(bucket))?;
+        dispute_aging_writer.flush()?;
+    }
+    if let Some(snapshot) = options.save_snapshot {
+        engine.save_snapshot(snapshot)?;
+    }
+    if let Some(mut alerts_writer) = alerts_writer {
+        alerts_writer.flush()?;
+    }
+    if let Some(mut journal_writer) = journal_writer {
+        journal_writer.flush()?;
+    }
+
+    Ok((engine, metrics))
+}
+
+/// Apply an admin transaction, writing its journal entry
+fn apply_admin<W: io::Write>(
+    engine: &mut Engine,
+    metrics: &mut Metrics,
+    transaction: &AdminInput,
+    check: Result<(), Rejection>,
+    alerts_writer: Option<&mut csv::Writer<W>>,
+    journal_writer: Option<&mut csv::Writer<W>>,
+    ledger_writer: Option<&mut csv::Writer<W>>,
+) -> Result<(), Error> {
+    let journal_type = JournalType::Admin(transaction.action);
+
+    let outcome = engine.apply_admin_checked(transaction, check);
+    write_postings(engine, transaction.client, ledger_writer)?;
+    record_metrics(metrics, transaction.action.name(), &outcome);
+    let outcome = outcome.map_err(|rejection| {
+        let (client, tx, amount) = (transaction.client, transaction.tx, transaction.amount);
+        rejected_entry(engine, client, journal_type, tx, amount, rejection)
+            .with_reason(transaction.reason.clone())
+    });
+    record(outcome, alerts_writer, journal_writer)
+}
+
+/// Admin rows among timestamped client rows need a timestamp to say where they take effect
+fn check_admin_timestamp(transaction: &AdminInput, interleaved: bool) -> Result<(), Rejection> {
+    if interleaved && transaction.timestamp.is_none() {
+        Err(Rejection::MissingTimestamp)
+    } else {
+        Ok(())
+    }
+}
+
+/// Export the ledger postings made by the client's last transaction as they happen
+fn write_postings<W: io::Write>(
+    engine: &Engine,
+    client: u16,
+    ledger_writer: Option<&mut csv::Writer<W>>,
+) -> Result<(), Error> {
+    if let Some(ledger_writer) = ledger_writer {
+        engine
+            .postings(client)
+            .iter()
+            .try_for_each(|posting| ledger_writer.serialize(posting))?;
+    }
+    Ok(())
+}
+
+fn record_metrics(
+    metrics: &mut Metrics,
+    transaction_type: &'static str,
+    outcome: &Result<Outcome, Rejection>,
+) {
+    match outcome {
+        Ok(outcome) => metrics.record_applied(transaction_type, outcome.amount),
+        Err(rejection) => metrics.record_rejected(transaction_type, *rejection),
+    }
+}
+
+/// Journal entry for a rejected transaction, the account is always opened by then
+fn rejected_entry(
+    engine: &Engine,
+    client: u16,
+    journal_type: JournalType,
+    tx: u32,
+    amount: Option<f64>,
+    rejection: Rejection,
+) -> JournalEntry {
+    let account = engine
+        .account(client)
+        .expect("the engine opens an account for every transaction");
+    JournalEntry::new(journal_type, tx, amount, &Err(rejection), account)
+}
+
+/// Write the alerts raised and the journal entry for an applied or rejected transaction
+fn record<W: io::Write>(
+    outcome: Result<Outcome, JournalEntry>,
+    alerts_writer: Option<&mut csv::Writer<W>>,
+    journal_writer: Option<&mut csv::Writer<W>>,
+) -> Result<(), Error> {
+    let (journal, alerts) = match outcome {
+        Ok(outcome) => (outcome.journal, outcome.alerts),
+        Err(journal) => (journal, Vec::new()),
+    };
+
+    match alerts_writer {
+        Some(alerts_writer) => alerts
+            .iter()
+            .try_for_each(|alert| alerts_writer.serialize(alert))?,
+        None => alerts.iter().for_each(|alert| {
+            warn!(
+                client = alert.client,
+                tx = alert.tx,
+                rule = alert.rule,
+                detail = %alert.detail,
+                locked = alert.locked,
+                "alert raised"
+            )
+        }),
+    }
+    if let Some(journal_writer) = journal_writer {
+        journal_writer.serialize(journal)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Output the test can read back once the run has finished with it
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn reader(input: &'static str) -> csv::Reader<Box<dyn io::Read>> {
+        csv_reader_builder().from_reader(Box::new(input.as_bytes()))
+    }
+
+    #[test]
+    fn apply_admin_transactions_from_their_timestamp() {
+        let transactions = "type,client,tx,amount,timestamp\n\
+            deposit,1,1,10.0,100\n\
+            dispute,1,1,,101\n\
+            chargeback,1,1,,102\n\
+            deposit,1,2,5.0,200\n";
+        let admin = "type,client,tx,amount,reason,timestamp\n\
+            unlock,1,50,,reviewed,150\n\
+            adjust,1,51,1.5,goodwill,\n\
+            adjust,1,52,2.0,goodwill,300\n";
+        let output = Output::default();
+        let journal = Output::default();
+        let options = Options {
+            output: Some(Box::new(output.clone())),
+            journal: Some(Box::new(journal.clone())),
+            admin: Some(reader(admin)),
+            policy: Policy::from_toml("[locking]\nreject_when_locked = true").unwrap(),
+            ..Options::default()
+        };
+
+        process_transaction_file(reader(transactions), options).unwrap();
+
+        // The unlock lets the later deposit through, the adjustment without a timestamp can
+        // not be placed among the client rows and the last one is applied at the end
+        let output = String::from_utf8(output.0.take()).unwrap();
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n1,7.0,0.0,7.0,false\n"
+        );
+        let journal = String::from_utf8(journal.0.take()).unwrap();
+        let rejected: Vec<&str> = journal
+            .lines()
+            .filter(|line| line.contains("missing_timestamp"))
+            .collect();
+        assert_eq!(rejected.len(), 1);
+        assert!(rejected[0].starts_with("1,51,admin,adjust,"));
+    }
+
+    #[test]
+    fn stop_at_an_unreadable_admin_row_once_it_is_due() {
+        let transactions = "type,client,tx,amount,timestamp\n\
+            deposit,1,1,10.0,100\n\
+            deposit,1,2,5.0,200\n";
+        let admin = "type,client,tx,amount,reason,timestamp\n\
+            unlock,1,50,,reviewed,150\n\
+            refund,1,51,1.5,goodwill,150\n";
+        let journal = Output::default();
+        let options = Options {
+            output: Some(Box::new(Output::default())),
+            journal: Some(Box::new(journal.clone())),
+            admin: Some(reader(admin)),
+            ..Options::default()
+        };
+
+        assert!(process_transaction_file(reader(transactions), options).is_err());
+
+        // The run stops before the client row the bad admin row was due ahead of
+        let journal = String::from_utf8(journal.0.take()).unwrap();
+        assert_eq!(journal.lines().count(), 3);
+        assert!(!journal.lines().any(|line| line.starts_with("1,2,")));
+    }
+
+    #[test]
+    fn export_the_ledger_postings_in_transaction_order() {
+        let transactions = "type,client,tx,amount\n\
+            deposit,1,1,10.0\n\
+            deposit,2,2,5.0\n\
+            withdrawal,1,3,4.0\n\
+            withdrawal,2,4,50.0\n";
+        let ledger = Output::default();
+        let options = Options {
+            output: Some(Box::new(Output::default())),
+            ledger: Some(Box::new(ledger.clone())),
+            ..Options::default()
+        };
+
+        process_transaction_file(reader(transactions), options).unwrap();
+
+        let ledger = String::from_utf8(ledger.0.take()).unwrap();
+        assert_eq!(
+            ledger,
+            "client,tx,account,debit,credit\n\
+            1,1,house_cash,10.0,\n\
+            1,1,client_available,,10.0\n\
+            2,2,house_cash,5.0,\n\
+            2,2,client_available,,5.0\n\
+            1,3,client_available,4.0,\n\
+            1,3,house_cash,,4.0\n"
+        );
+    }
+}
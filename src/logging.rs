@@ -0,0 +1,23 @@
+use crate::Error;
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+/// Log to stderr at the given level, `RUST_LOG` is used when no level is given and the
+/// default is info. The format is `text` or `json`, one object per line.
+pub fn init_logging(level: Option<&str>, format: Option<&str>) -> Result<(), Error> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+
+    match format {
+        None | Some("text") => subscriber.init(),
+        Some("json") => subscriber.json().init(),
+        Some(format) => return Err(format!("unknown log format {}", format).into()),
+    }
+    Ok(())
+}
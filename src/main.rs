@@ -1,39 +1,393 @@
-use csv::{ReaderBuilder, Trim};
 use std::env;
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
 use std::process;
-use toy_transaction::process_transaction_file;
+use toy_transaction::transaction_engine::{
+    parse_timestamp, JsonListener, Listeners, LogListener, Policy,
+};
+use toy_transaction::{
+    init_logging, open_csv, open_disputes_file, process_transaction_file,
+    reconcile_transaction_file, Dialect, Error, Inputs, Limits, Metrics, Options, Rules,
+};
+use tracing::{error, info, warn};
+
+const USAGE: &str = "Usage: toy_transaction [reconcile|open-disputes] <transactions.csv|dir|pattern>... \
+[--dialect <dialect.toml>] [--policy <policy.toml>] [--limits <limits.toml>] [--rules <rules.toml>] [--alerts <alerts.csv>] [--admin <admin.csv>] \
+[--journal <journal.csv>] [--ledger <ledger.csv>] [--negative-balances <negative.csv>]
+       [--open-disputes <open_disputes.csv>] [--dispute-aging <aging.csv>] [--as-of <timestamp>]
+       [--load-snapshot <snapshot.bin>] [--save-snapshot <snapshot.bin>]
+       [--events <events.jsonl>] [--event-log <events.log>]
+       [--log-level <error|warn|info|debug|trace>] [--log-format <text|json>]
+       [--metrics <metrics.prom>] [--metrics-format <prometheus|json>]
+       reconcile options: --expected <expected.csv> [--tolerance <amount>]";
+
+#[derive(Default)]
+struct Config<'a> {
+    reconcile: bool,
+    /// Write the open disputes rather than the balances
+    open_disputes_report: bool,
+    /// Transaction files, directories of them or patterns matching them, read in order
+    transaction_files: Vec<&'a str>,
+    expected_file: Option<&'a str>,
+    tolerance: Option<&'a str>,
+    dialect_file: Option<&'a str>,
+    policy_file: Option<&'a str>,
+    limits_file: Option<&'a str>,
+    rules_file: Option<&'a str>,
+    alerts_file: Option<&'a str>,
+    admin_file: Option<&'a str>,
+    journal_file: Option<&'a str>,
+    ledger_file: Option<&'a str>,
+    negative_balances_file: Option<&'a str>,
+    open_disputes_file: Option<&'a str>,
+    dispute_aging_file: Option<&'a str>,
+    as_of: Option<&'a str>,
+    load_snapshot_file: Option<&'a str>,
+    save_snapshot_file: Option<&'a str>,
+    events_file: Option<&'a str>,
+    event_log_file: Option<&'a str>,
+    log_level: Option<&'a str>,
+    log_format: Option<&'a str>,
+    metrics_file: Option<&'a str>,
+    metrics_format: Option<&'a str>,
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let transaction_input = parse_config(&args);
-    let transaction_input = parse_csv_reader(transaction_input);
+    let config = parse_config(&args);
+    init_logging(config.log_level, config.log_format).unwrap_or_else(|_| exit_with_usage());
+    let options = Options {
+        output: None,
+        limits: parse_limits(config.limits_file),
+        policy: parse_policy(config.policy_file),
+        rules: parse_rules(config.rules_file),
+        admin: config.admin_file.map(parse_admin_reader),
+        alerts: parse_output_writer(config.alerts_file),
+        journal: parse_output_writer(config.journal_file),
+        ledger: parse_output_writer(config.ledger_file),
+        negative_balances: parse_output_writer(config.negative_balances_file),
+        open_disputes: parse_output_writer(config.open_disputes_file),
+        dispute_aging: parse_output_writer(config.dispute_aging_file),
+        listeners: parse_listeners(&config),
+        as_of: config
+            .as_of
+            .map(|as_of| parse_timestamp(as_of).unwrap_or_else(|_| exit_with_usage())),
+        load_snapshot: config.load_snapshot_file.map(parse_snapshot_reader),
+        save_snapshot: config.save_snapshot_file.map(parse_snapshot_writer),
+    };
+    let transaction_input = parse_inputs(&config.transaction_files, config.dialect_file);
 
-    match process_transaction_file(transaction_input) {
-        Ok(()) => eprintln!("Finished"),
-        Err(err) => eprintln!("An application error occurred {:#?}", err),
+    if config.reconcile {
+        reconcile(transaction_input, &config, options);
+        return;
+    }
+
+    let result = if config.open_disputes_report {
+        open_disputes_file(transaction_input, options)
+    } else {
+        process_transaction_file(transaction_input, options)
+    };
+    match result {
+        Ok(metrics) => {
+            commit_snapshot(config.save_snapshot_file);
+            report_metrics(&metrics, &config);
+            info!("finished")
+        }
+        Err(err) => {
+            error!(error = %err, "an application error occurred");
+            process::exit(3)
+        }
     };
 }
 
-fn parse_config(args: &[String]) -> &str {
-    if args.len() != 2 {
-        eprintln!("Please provide a transaction file to process ONLY");
-        process::exit(1);
+fn reconcile(transaction_input: Inputs, config: &Config, options: Options) {
+    let expected_input =
+        parse_csv_reader(config.expected_file.unwrap_or_else(|| exit_with_usage()));
+    let tolerance = match config.tolerance.map(str::parse::<f64>) {
+        None => 0.0,
+        Some(Ok(tolerance)) if tolerance >= 0.0 => tolerance,
+        Some(_) => exit_with_usage(),
     };
 
-    &args[1]
+    let metrics =
+        match reconcile_transaction_file(transaction_input, expected_input, tolerance, options) {
+            Ok(metrics) => {
+                commit_snapshot(config.save_snapshot_file);
+                metrics
+            }
+            Err(err) => {
+                error!(error = %err, "an application error occurred");
+                process::exit(3)
+            }
+        };
+
+    report_metrics(&metrics, config);
+    match metrics.differences {
+        Some(0) | None => info!("reconciled"),
+        Some(differences) => {
+            warn!(differences, "reconciliation found differences");
+            process::exit(4)
+        }
+    }
 }
 
-fn parse_csv_reader(csv_file_location: &str) -> csv::Reader<File> {
-    match ReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .from_path(Path::new(csv_file_location))
+/// Log the run summary, print it for text logs and write the metrics file when asked for
+fn report_metrics(metrics: &Metrics, config: &Config) {
+    info!(
+        rows_read = metrics.rows_read,
+        skipped_rows = metrics.skipped_rows,
+        clients = metrics.clients,
+        locked_accounts = metrics.locked_accounts,
+        negative_accounts = metrics.negative_accounts,
+        expired_disputes = metrics.expired_disputes,
+        open_disputes = metrics.open_disputes,
+        deposited = metrics.deposited,
+        withdrawn = metrics.withdrawn,
+        charged_back = metrics.charged_back,
+        elapsed_seconds = metrics.elapsed_seconds,
+        policy = %metrics.policy,
+        "run summary"
+    );
+    if config.log_format != Some("json") {
+        eprintln!("{}", metrics);
+    }
+
+    let metrics_file = match config.metrics_file {
+        Some(metrics_file) => metrics_file,
+        None => return,
+    };
+    let output = match config.metrics_format {
+        Some("json") => serde_json::to_string_pretty(metrics).map_err(|err| err.into()),
+        _ => Ok(metrics.to_prometheus()),
+    };
+
+    if let Err(err) = output.and_then(|output| fs::write(metrics_file, output).map_err(Error::from))
     {
+        error!(path = metrics_file, error = %err, "failed to write metrics file");
+        process::exit(2)
+    }
+}
+
+fn parse_config(args: &[String]) -> Config<'_> {
+    let mut config = Config::default();
+    let mut args = args.iter().skip(1).map(String::as_str).peekable();
+
+    match args.peek() {
+        Some(&"reconcile") => config.reconcile = true,
+        Some(&"open-disputes") => config.open_disputes_report = true,
+        _ => (),
+    }
+    if config.reconcile || config.open_disputes_report {
+        args.next();
+    }
+
+    while let Some(arg) = args.next() {
+        let option = match arg {
+            "--expected" if config.reconcile => &mut config.expected_file,
+            "--tolerance" if config.reconcile => &mut config.tolerance,
+            "--dialect" => &mut config.dialect_file,
+            "--policy" => &mut config.policy_file,
+            "--limits" => &mut config.limits_file,
+            "--rules" => &mut config.rules_file,
+            "--alerts" => &mut config.alerts_file,
+            "--admin" => &mut config.admin_file,
+            "--journal" => &mut config.journal_file,
+            "--ledger" => &mut config.ledger_file,
+            "--negative-balances" => &mut config.negative_balances_file,
+            "--open-disputes" => &mut config.open_disputes_file,
+            "--dispute-aging" => &mut config.dispute_aging_file,
+            "--load-snapshot" => &mut config.load_snapshot_file,
+            "--save-snapshot" => &mut config.save_snapshot_file,
+            "--as-of" if !config.reconcile && !config.open_disputes_report => &mut config.as_of,
+            "--events" => &mut config.events_file,
+            "--event-log" => &mut config.event_log_file,
+            "--log-level" => &mut config.log_level,
+            "--log-format" => &mut config.log_format,
+            "--metrics" => &mut config.metrics_file,
+            "--metrics-format" => &mut config.metrics_format,
+            _ if !arg.starts_with("--") => {
+                config.transaction_files.push(arg);
+                continue;
+            }
+            _ => exit_with_usage(),
+        };
+
+        match args.next() {
+            Some(value) if option.is_none() => *option = Some(value),
+            _ => exit_with_usage(),
+        }
+    }
+
+    if config.transaction_files.is_empty() {
+        exit_with_usage();
+    }
+    if !matches!(
+        config.metrics_format,
+        None | Some("prometheus") | Some("json")
+    ) {
+        exit_with_usage();
+    }
+
+    config
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_policy(policy_file_location: Option<&str>) -> Policy {
+    let policy_file_location = match policy_file_location {
+        Some(location) => location,
+        None => return Policy::default(),
+    };
+
+    match fs::read_to_string(policy_file_location)
+        .map_err(|err| err.into())
+        .and_then(|input| Policy::from_toml(&input))
+    {
+        Ok(policy) => policy,
+        Err(err) => {
+            error!(path = policy_file_location, error = %err, "failed to load policy file");
+            process::exit(2)
+        }
+    }
+}
+
+fn parse_limits(limits_file_location: Option<&str>) -> Limits {
+    let limits_file_location = match limits_file_location {
+        Some(location) => location,
+        None => return Limits::default(),
+    };
+
+    match fs::read_to_string(limits_file_location)
+        .map_err(|err| err.into())
+        .and_then(|input| Limits::from_toml(&input))
+    {
+        Ok(limits) => limits,
+        Err(err) => {
+            error!(path = limits_file_location, error = %err, "failed to load limits file");
+            process::exit(2)
+        }
+    }
+}
+
+fn parse_rules(rules_file_location: Option<&str>) -> Rules {
+    let rules_file_location = match rules_file_location {
+        Some(location) => location,
+        None => return Rules::default(),
+    };
+
+    match fs::read_to_string(rules_file_location)
+        .map_err(|err| err.into())
+        .and_then(|input| Rules::from_toml(&input))
+    {
+        Ok(rules) => rules,
+        Err(err) => {
+            error!(path = rules_file_location, error = %err, "failed to load rules file");
+            process::exit(2)
+        }
+    }
+}
+
+fn parse_output_writer(output_file_location: Option<&str>) -> Option<Box<dyn Write>> {
+    let output_file_location = output_file_location?;
+
+    match File::create(output_file_location) {
+        Ok(output) => Some(Box::new(output)),
+        Err(err) => {
+            error!(path = output_file_location, error = %err, "failed to create output file");
+            process::exit(2)
+        }
+    }
+}
+
+/// The snapshot is read up front, so a run can save its snapshot over the one it loaded
+fn parse_snapshot_reader(snapshot_file_location: &str) -> Box<dyn Read> {
+    match fs::read(snapshot_file_location) {
+        Ok(snapshot) => Box::new(io::Cursor::new(snapshot)),
+        Err(err) => {
+            error!(path = snapshot_file_location, error = %err, "failed to read snapshot file");
+            process::exit(2)
+        }
+    }
+}
+
+/// The snapshot is written beside its final location and only moved into place once the run
+/// succeeds, so a failed run leaves the previous snapshot as it was
+fn parse_snapshot_writer(snapshot_file_location: &str) -> Box<dyn Write> {
+    let output = parse_output_writer(Some(&partial_snapshot_file(snapshot_file_location)));
+    Box::new(BufWriter::new(output.expect("an output file is created")))
+}
+
+fn commit_snapshot(snapshot_file_location: Option<&str>) {
+    let snapshot_file_location = match snapshot_file_location {
+        Some(location) => location,
+        None => return,
+    };
+
+    let partial = partial_snapshot_file(snapshot_file_location);
+    if let Err(err) = fs::rename(&partial, snapshot_file_location) {
+        error!(path = snapshot_file_location, error = %err, "failed to write snapshot file");
+        process::exit(2)
+    }
+}
+
+fn partial_snapshot_file(snapshot_file_location: &str) -> String {
+    format!("{}.partial", snapshot_file_location)
+}
+
+fn parse_listeners(config: &Config) -> Listeners {
+    let mut listeners = Listeners::new();
+    if let Some(events) = parse_output_writer(config.events_file) {
+        listeners = listeners.with_listener(JsonListener::new(BufWriter::new(events)));
+    }
+    if let Some(event_log) = parse_output_writer(config.event_log_file) {
+        listeners = listeners.with_listener(LogListener::new(BufWriter::new(event_log)));
+    }
+    listeners
+}
+
+fn parse_admin_reader(admin_file_location: &str) -> csv::Reader<Box<dyn Read>> {
+    match open_csv(admin_file_location) {
+        Ok(input) => input,
+        Err(err) => {
+            error!(path = admin_file_location, error = %err, "failed to open admin csv file");
+            process::exit(2)
+        }
+    }
+}
+
+fn parse_inputs(transaction_files: &[&str], dialect_file: Option<&str>) -> Inputs<'static> {
+    let inputs = match Inputs::from_paths(transaction_files) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            error!(error = %err, "failed to find transaction files");
+            process::exit(2)
+        }
+    };
+    let dialect_file_location = match dialect_file {
+        Some(location) => location,
+        None => return inputs,
+    };
+
+    match fs::read_to_string(dialect_file_location)
+        .map_err(|err| err.into())
+        .and_then(|input| Dialect::from_toml(&input))
+    {
+        Ok(dialect) => inputs.with_dialect(dialect),
+        Err(err) => {
+            error!(path = dialect_file_location, error = %err, "failed to load dialect file");
+            process::exit(2)
+        }
+    }
+}
+
+fn parse_csv_reader(csv_file_location: &str) -> csv::Reader<Box<dyn Read>> {
+    match open_csv(csv_file_location) {
         Ok(input) => input,
         Err(err) => {
-            eprintln!("Failed to open csv file {:#?}", err);
+            error!(path = csv_file_location, error = %err, "failed to open csv file");
             process::exit(2)
         }
     }
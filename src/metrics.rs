@@ -0,0 +1,340 @@
+use crate::transaction_engine::{
+    optional_setting, Balance, JournalEntry, JournalType, Policy, Rejection,
+    TransactionRunningState, TransactionType,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+/// Counters and timings collected over a run.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    /// Client transaction rows read, admin rows are only counted by type
+    pub rows_read: u64,
+    /// Malformed client transaction rows skipped, only when the policy is not strict
+    pub skipped_rows: u64,
+    /// Applied transactions by type
+    pub applied: BTreeMap<&'static str, u64>,
+    /// Rejected transactions by type
+    pub rejected: BTreeMap<&'static str, u64>,
+    /// Rejected transactions by reason code
+    pub rejections: BTreeMap<&'static str, u64>,
+    pub clients: u64,
+    pub locked_accounts: u64,
+    /// Accounts left in negative available or total funds
+    pub negative_accounts: u64,
+    /// Disputes resolved or charged back by the policy's expiry
+    pub expired_disputes: u64,
+    /// Disputes still open at the end of the run
+    pub open_disputes: u64,
+    pub deposited: f64,
+    pub withdrawn: f64,
+    pub charged_back: f64,
+    pub elapsed_seconds: f64,
+    pub rows_per_second: f64,
+    /// Number of differences found, only set when reconciling
+    pub differences: Option<usize>,
+    /// The policy the run was made with
+    pub policy: Policy,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn record_applied(&mut self, transaction_type: &'static str, amount: f64) {
+        *self.applied.entry(transaction_type).or_insert(0) += 1;
+
+        if transaction_type == TransactionType::Deposit.name() {
+            self.deposited += amount;
+        } else if transaction_type == TransactionType::Withdrawal.name() {
+            self.withdrawn += amount;
+        } else if transaction_type == TransactionType::Chargeback.name() {
+            self.charged_back += amount;
+        }
+    }
+
+    /// Record the synthetic resolves and chargebacks of expired disputes
+    pub fn record_expired(&mut self, entries: &[JournalEntry]) {
+        for entry in entries.iter().filter(|entry| entry.rejection.is_none()) {
+            self.expired_disputes += 1;
+            if entry.journal_type == JournalType::Expiry(TransactionType::Chargeback) {
+                self.charged_back += entry.amount.unwrap_or(0.0);
+            }
+        }
+    }
+
+    pub fn record_rejected(&mut self, transaction_type: &'static str, rejection: Rejection) {
+        *self.rejected.entry(transaction_type).or_insert(0) += 1;
+        *self.rejections.entry(rejection.code()).or_insert(0) += 1;
+    }
+
+    /// Record the final accounts and how long the run took
+    pub fn finish<'a, I>(&mut self, accounts: I, elapsed: Duration)
+    where
+        I: IntoIterator<Item = &'a TransactionRunningState>,
+    {
+        for account in accounts {
+            self.clients += 1;
+            if account.locked() {
+                self.locked_accounts += 1;
+            }
+            if Balance::from_account(account).is_negative() {
+                self.negative_accounts += 1;
+            }
+        }
+        self.elapsed_seconds = elapsed.as_secs_f64();
+        self.rows_per_second = if self.elapsed_seconds > 0.0 {
+            self.rows_read as f64 / self.elapsed_seconds
+        } else {
+            0.0
+        };
+    }
+
+    /// Metrics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+        // Writing to a String can not fail
+        let _ = self.write_prometheus(&mut output);
+        output
+    }
+
+    fn write_prometheus(&self, output: &mut String) -> fmt::Result {
+        header(
+            output,
+            "rows_read_total",
+            "counter",
+            "Client transaction rows read",
+        )?;
+        writeln!(output, "toy_transaction_rows_read_total {}", self.rows_read)?;
+
+        header(
+            output,
+            "skipped_rows_total",
+            "counter",
+            "Malformed client transaction rows skipped",
+        )?;
+        writeln!(
+            output,
+            "toy_transaction_skipped_rows_total {}",
+            self.skipped_rows
+        )?;
+
+        header(
+            output,
+            "expired_disputes_total",
+            "counter",
+            "Disputes resolved or charged back by expiry",
+        )?;
+        writeln!(
+            output,
+            "toy_transaction_expired_disputes_total {}",
+            self.expired_disputes
+        )?;
+
+        header(
+            output,
+            "transactions_total",
+            "counter",
+            "Transactions by type and result",
+        )?;
+        for (result, counts) in [("applied", &self.applied), ("rejected", &self.rejected)].iter() {
+            for (transaction_type, count) in counts.iter() {
+                writeln!(
+                    output,
+                    "toy_transaction_transactions_total{{type=\"{}\",result=\"{}\"}} {}",
+                    transaction_type, result, count
+                )?;
+            }
+        }
+
+        header(
+            output,
+            "rejections_total",
+            "counter",
+            "Rejected transactions by reason",
+        )?;
+        for (reason, count) in &self.rejections {
+            writeln!(
+                output,
+                "toy_transaction_rejections_total{{reason=\"{}\"}} {}",
+                reason, count
+            )?;
+        }
+
+        header(output, "amount_total", "counter", "Amounts moved by kind")?;
+        for (kind, amount) in [
+            ("deposited", self.deposited),
+            ("withdrawn", self.withdrawn),
+            ("charged_back", self.charged_back),
+        ]
+        .iter()
+        {
+            writeln!(
+                output,
+                "toy_transaction_amount_total{{kind=\"{}\"}} {}",
+                kind, amount
+            )?;
+        }
+
+        let mut gauges = vec![
+            ("clients", "Clients with an account", self.clients as f64),
+            (
+                "locked_accounts",
+                "Locked accounts",
+                self.locked_accounts as f64,
+            ),
+            (
+                "negative_accounts",
+                "Accounts in negative available or total funds",
+                self.negative_accounts as f64,
+            ),
+            (
+                "open_disputes",
+                "Disputes still open at the end of the run",
+                self.open_disputes as f64,
+            ),
+            ("run_seconds", "Duration of the run", self.elapsed_seconds),
+            (
+                "rows_per_second",
+                "Rows processed per second",
+                self.rows_per_second,
+            ),
+        ];
+        if let Some(differences) = self.differences {
+            gauges.push((
+                "differences",
+                "Reconciliation differences",
+                differences as f64,
+            ));
+        }
+        for (name, help, value) in gauges {
+            header(output, name, "gauge", help)?;
+            writeln!(output, "toy_transaction_{} {}", name, value)?;
+        }
+
+        let policy = &self.policy;
+        header(
+            output,
+            "policy_info",
+            "gauge",
+            "Policy the run was made with",
+        )?;
+        writeln!(
+            output,
+            "toy_transaction_policy_info{{precision=\"{}\",rounding=\"{}\",zero=\"{}\",\
+             allow_overdraft=\"{}\",lock_on_chargeback=\"{}\",reject_when_locked=\"{}\",\
+             withdrawal_disputes=\"{}\",spent_deposits=\"{}\",dispute_window_days=\"{}\",\
+             expire_after_transactions=\"{}\",expire_after_days=\"{}\",on_expiry=\"{}\",\
+             duplicates=\"{}\",strict=\"{}\",ordering=\"{}\"}} 1",
+            policy.amounts.precision,
+            policy.amounts.rounding.name(),
+            policy.amounts.zero.name(),
+            policy.amounts.allow_overdraft,
+            policy.locking.lock_on_chargeback,
+            policy.locking.reject_when_locked,
+            policy.disputes.withdrawals.name(),
+            policy.disputes.spent_deposits.name(),
+            optional_setting(policy.disputes.window_days),
+            optional_setting(policy.disputes.expire_after_transactions),
+            optional_setting(policy.disputes.expire_after_days),
+            policy.disputes.on_expiry.name(),
+            policy.input.duplicates.name(),
+            policy.input.strict,
+            policy.input.ordering.name(),
+        )?;
+
+        Ok(())
+    }
+}
+
+fn header(output: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(output, "# HELP toy_transaction_{} {}", name, help)?;
+    writeln!(output, "# TYPE toy_transaction_{} {}", name, kind)
+}
+
+/// Human readable run summary
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Run summary")?;
+        writeln!(f, "  rows read:       {}", self.rows_read)?;
+        if self.skipped_rows > 0 {
+            writeln!(f, "  skipped rows:    {}", self.skipped_rows)?;
+        }
+        writeln!(f, "  clients:         {}", self.clients)?;
+        writeln!(f, "  locked accounts: {}", self.locked_accounts)?;
+        if self.negative_accounts > 0 {
+            writeln!(f, "  negative accounts: {}", self.negative_accounts)?;
+        }
+        if self.expired_disputes > 0 {
+            writeln!(f, "  expired disputes: {}", self.expired_disputes)?;
+        }
+        if self.open_disputes > 0 {
+            writeln!(f, "  open disputes:   {}", self.open_disputes)?;
+        }
+        for (transaction_type, count) in &self.applied {
+            let rejected = self.rejected.get(transaction_type).copied().unwrap_or(0);
+            writeln!(
+                f,
+                "  {:<16} {} applied, {} rejected",
+                format!("{}:", transaction_type),
+                count,
+                rejected
+            )?;
+        }
+        for (transaction_type, rejected) in &self.rejected {
+            if !self.applied.contains_key(transaction_type) {
+                writeln!(
+                    f,
+                    "  {:<16} 0 applied, {} rejected",
+                    format!("{}:", transaction_type),
+                    rejected
+                )?;
+            }
+        }
+        for (reason, count) in &self.rejections {
+            writeln!(f, "  rejected {:<22} {}", format!("{}:", reason), count)?;
+        }
+        writeln!(f, "  deposited:       {:.4}", self.deposited)?;
+        writeln!(f, "  withdrawn:       {:.4}", self.withdrawn)?;
+        writeln!(f, "  charged back:    {:.4}", self.charged_back)?;
+        if let Some(differences) = self.differences {
+            writeln!(f, "  differences:     {}", differences)?;
+        }
+        writeln!(f, "  policy:          {}", self.policy)?;
+        write!(
+            f,
+            "  elapsed:         {:.3}s ({:.0} rows/s)",
+            self.elapsed_seconds, self.rows_per_second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_applied_and_rejected_transactions() {
+        let mut metrics = Metrics::new();
+        metrics.rows_read = 3;
+        metrics.record_applied("deposit", 10.0);
+        metrics.record_applied("chargeback", 4.0);
+        metrics.record_rejected("withdrawal", Rejection::InsufficientFunds);
+        metrics.finish(&[], Duration::from_secs(1));
+
+        assert_eq!(metrics.deposited, 10.0);
+        assert_eq!(metrics.charged_back, 4.0);
+        assert_eq!(metrics.rejections.get("insufficient_funds"), Some(&1));
+        assert_eq!(metrics.rows_per_second, 3.0);
+
+        let prometheus = metrics.to_prometheus();
+        assert!(prometheus.contains(
+            "toy_transaction_transactions_total{type=\"withdrawal\",result=\"rejected\"} 1"
+        ));
+        assert!(prometheus
+            .contains("toy_transaction_rejections_total{reason=\"insufficient_funds\"} 1"));
+    }
+}
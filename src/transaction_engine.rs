@@ -1,9 +1,44 @@
+mod admin;
+mod balance;
+mod disputes;
+mod engine;
+mod journal;
+mod ledger;
+mod limits;
+mod listener;
+mod negative_balance;
+mod open_dispute;
+mod policy;
 mod processor;
-mod processor_state;
+mod reconcile;
+mod rejection;
+mod rules;
+mod snapshot;
 mod transaction;
 mod transaction_running_state;
 
-pub use processor::TransactionProcessor;
-pub use processor_state::TransactionEngineProcessorState;
-pub use transaction::{TransactionInput, TransactionType};
+pub use admin::{AdminAction, AdminInput};
+pub use balance::Balance;
+pub use disputes::{DisputeStatus, Disputes, Moment, Settled};
+pub use engine::Engine;
+pub use journal::{JournalEntry, JournalType};
+pub use ledger::{Account, Ledger, Posting, TrialBalance};
+pub use limits::{ClientLimits, Limit, LimitTracker, Limits};
+pub use listener::{
+    Event, EventKind, EventTransaction, JsonListener, Listener, Listeners, LogListener,
+};
+pub use negative_balance::negative_balances;
+pub use open_dispute::{dispute_aging, AgingBucket, OpenDispute};
+pub(crate) use policy::optional_setting;
+pub use policy::{
+    AmountPolicy, DisputePolicy, Duplicates, ExpiryAction, InputPolicy, LockingPolicy, Policy,
+    Precision, Rounding, RowOrder, SpentDeposits, WithdrawalDisputes, ZeroAmounts,
+};
+pub use processor::{Outcome, TransactionProcessor};
+pub use reconcile::{reconcile, Difference};
+pub use rejection::Rejection;
+pub use rules::{Alert, History, Rules};
+pub use snapshot::SNAPSHOT_VERSION;
+use snapshot::{AccountSnapshot, SettledSnapshot, Snapshot, Time, TransactionSnapshot};
+pub use transaction::{parse_timestamp, Timestamp, TransactionInput, TransactionType};
 pub use transaction_running_state::TransactionRunningState;
@@ -0,0 +1,49 @@
+use super::transaction::deserialize_timestamp;
+use super::Timestamp;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminAction {
+    Unlock,
+    Lock,
+    Adjust,
+}
+
+impl AdminAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminAction::Unlock => "unlock",
+            AdminAction::Lock => "lock",
+            AdminAction::Adjust => "adjust",
+        }
+    }
+}
+
+/// An operator issued transaction, read from the admin input rather than the client feed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminInput {
+    #[serde(rename = "type")]
+    pub action: AdminAction,
+
+    #[serde(rename = "client")]
+    pub client: u16,
+
+    #[serde(rename = "tx")]
+    pub tx: u32,
+
+    /// Signed amount, only used by adjustments
+    #[serde(deserialize_with = "csv::invalid_option")]
+    #[serde(rename = "amount")]
+    pub amount: Option<f64>,
+
+    #[serde(deserialize_with = "csv::invalid_option")]
+    #[serde(rename = "reason")]
+    pub reason: Option<String>,
+
+    /// When the action takes effect among the client transactions, after all of them when not
+    /// given
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    #[serde(rename = "timestamp")]
+    pub timestamp: Option<Timestamp>,
+}
@@ -0,0 +1,31 @@
+use super::TransactionRunningState;
+use serde::{Deserialize, Serialize};
+
+/// The client's balances at the output precision, as written out at the end of the run or as
+/// they stood at some point in it, and as read back from a file of expected balances.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+impl Balance {
+    pub fn from_account(account: &TransactionRunningState) -> Self {
+        let precision = account.precision();
+        Balance {
+            client: account.client(),
+            available: precision.round(account.available()),
+            held: precision.round(account.held()),
+            total: precision.round(account.total()),
+            locked: account.locked(),
+        }
+    }
+
+    /// The client owes the house money, their available or total funds are negative
+    pub fn is_negative(&self) -> bool {
+        self.available < 0.0 || self.total < 0.0
+    }
+}
@@ -0,0 +1,216 @@
+use super::{Rejection, Timestamp, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Amounts within this share of the transaction amount are treated as equal, so splitting a
+/// transaction into partial disputes never strands a remainder made of rounding error
+const TOLERANCE: f64 = 1e-9;
+
+/// Snapshots store statuses by their position here, so new statuses go at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisputeStatus {
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+/// A point in the client's history, by the number of rows received before it and the row's
+/// timestamp, used to age disputes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moment {
+    pub row: usize,
+    pub timestamp: Option<Timestamp>,
+}
+
+/// A deposit or withdrawal that was applied to the account and so can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settled {
+    pub transaction_type: TransactionType,
+    pub amount: f64,
+    /// When the transaction happened, if the row had a timestamp
+    pub timestamp: Option<Timestamp>,
+    pub dispute: Option<DisputeStatus>,
+    /// When the current or last dispute was opened, disputing more of an open dispute keeps
+    /// the time it was first opened
+    pub opened: Option<Moment>,
+    /// Part of the amount under an open dispute
+    pub disputed: f64,
+    /// Part of the disputed amount held, or provisionally credited
+    pub held: f64,
+    /// Part of the amount charged back so far
+    pub charged_back: f64,
+}
+
+impl Settled {
+    /// Part of the amount that can still be disputed
+    pub fn undisputed(&self) -> f64 {
+        self.amount - self.disputed - self.charged_back
+    }
+
+    fn is_zero(&self, value: f64) -> bool {
+        value.abs() <= self.amount.abs() * TOLERANCE
+    }
+
+    /// The requested part of `available`, all of it when no amount is given. A request within
+    /// rounding error of `available` is taken as all of it.
+    fn portion(&self, requested: Option<f64>, available: f64) -> Result<f64, Rejection> {
+        let requested = match requested {
+            None => return Ok(available),
+            Some(requested) if requested > 0.0 && requested.is_finite() => requested,
+            Some(_) => return Err(Rejection::InvalidAmount),
+        };
+
+        if self.is_zero(requested - available) {
+            Ok(available)
+        } else if requested > available {
+            Err(Rejection::AmountTooLarge)
+        } else {
+            Ok(requested)
+        }
+    }
+}
+
+/// The client's settled transactions along with the dispute state of each.
+///
+/// A transaction can be disputed in parts, each part is resolved or charged back on its own
+/// and the total disputed never exceeds the transaction amount. A resolved part can be
+/// disputed again, a charged back part is final.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Disputes {
+    settled: HashMap<u32, Settled>,
+}
+
+impl Disputes {
+    pub fn new() -> Self {
+        Disputes {
+            settled: HashMap::new(),
+        }
+    }
+
+    /// Record an applied transaction, the first use of a transaction id wins
+    pub fn settle(
+        &mut self,
+        tx: u32,
+        transaction_type: TransactionType,
+        amount: f64,
+        timestamp: Option<Timestamp>,
+    ) {
+        self.settled.entry(tx).or_insert(Settled {
+            transaction_type,
+            amount,
+            timestamp,
+            dispute: None,
+            opened: None,
+            disputed: 0.0,
+            held: 0.0,
+            charged_back: 0.0,
+        });
+    }
+
+    /// Every settled transaction in transaction id order
+    pub fn settled(&self) -> Vec<(u32, Settled)> {
+        let mut settled: Vec<(u32, Settled)> = self
+            .settled
+            .iter()
+            .map(|(tx, settled)| (*tx, *settled))
+            .collect();
+        settled.sort_by_key(|(tx, _)| *tx);
+        settled
+    }
+
+    /// Carry a settled transaction over from a snapshot along with its dispute state
+    pub(crate) fn restore(&mut self, tx: u32, settled: Settled) {
+        self.settled.insert(tx, settled);
+    }
+
+    pub fn find(&self, tx: u32) -> Result<Settled, Rejection> {
+        self.settled
+            .get(&tx)
+            .copied()
+            .ok_or(Rejection::TransactionNotFound)
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.settled
+            .values()
+            .filter(|settled| settled.dispute == Some(DisputeStatus::Open))
+            .count()
+    }
+
+    /// Transactions under an open dispute, in transaction id order
+    pub fn open_disputes(&self) -> Vec<(u32, Settled)> {
+        let mut open = self.settled();
+        open.retain(|(_, settled)| settled.dispute == Some(DisputeStatus::Open));
+        open
+    }
+
+    /// Check the requested part of the transaction can be disputed, returning the transaction
+    /// along with the amount to dispute, the whole undisputed amount when none is requested
+    pub fn check_can_open(
+        &self,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> Result<(Settled, f64), Rejection> {
+        let settled = self.find(tx)?;
+        if settled.is_zero(settled.undisputed()) {
+            return match settled.dispute {
+                Some(DisputeStatus::Open) => Err(Rejection::AlreadyDisputed),
+                _ => Err(Rejection::AlreadyChargedBack),
+            };
+        }
+
+        let amount = settled.portion(amount, settled.undisputed())?;
+        Ok((settled, amount))
+    }
+
+    /// Check the requested part of the open dispute can be resolved or charged back, returning
+    /// the transaction along with the amount, the whole disputed amount when none is requested
+    pub fn check_is_open(&self, tx: u32, amount: Option<f64>) -> Result<(Settled, f64), Rejection> {
+        let settled = self.find(tx)?;
+        if settled.dispute != Some(DisputeStatus::Open) {
+            return Err(Rejection::NotDisputed);
+        }
+
+        let amount = settled.portion(amount, settled.disputed)?;
+        Ok((settled, amount))
+    }
+
+    /// Dispute `amount` more of the transaction at `now`, of which `held` is held
+    pub fn open(&mut self, tx: u32, amount: f64, held: f64, now: Moment) {
+        if let Some(settled) = self.settled.get_mut(&tx) {
+            if settled.dispute != Some(DisputeStatus::Open) {
+                settled.opened = Some(now);
+            }
+            settled.dispute = Some(DisputeStatus::Open);
+            settled.disputed += amount;
+            settled.held += held;
+        }
+    }
+
+    /// Close `amount` of the open dispute with the transaction standing, releasing `released`
+    /// of the held amount
+    pub fn resolve(&mut self, tx: u32, amount: f64, released: f64) {
+        self.close(tx, amount, released, DisputeStatus::Resolved);
+    }
+
+    /// Charge back `amount` of the open dispute, `used` of which came from the held amount
+    pub fn charge_back(&mut self, tx: u32, amount: f64, used: f64) {
+        if let Some(settled) = self.settled.get_mut(&tx) {
+            settled.charged_back += amount;
+        }
+        self.close(tx, amount, used, DisputeStatus::ChargedBack);
+    }
+
+    fn close(&mut self, tx: u32, amount: f64, held: f64, status: DisputeStatus) {
+        if let Some(settled) = self.settled.get_mut(&tx) {
+            settled.disputed -= amount;
+            settled.held -= held;
+            // The dispute stays open until every disputed part is closed
+            if settled.is_zero(settled.disputed) {
+                settled.dispute = Some(status);
+                settled.disputed = 0.0;
+                settled.held = 0.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use super::{
+    dispute_aging, negative_balances, AdminAction, AdminInput, AgingBucket, Balance, Duplicates,
+    JournalEntry, Limits, Listeners, OpenDispute, Outcome, Policy, Posting, Rejection, Rules,
+    Snapshot, Timestamp, TransactionInput, TransactionProcessor, TransactionRunningState,
+    TransactionType, TrialBalance,
+};
+use crate::Error;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+use std::rc::Rc;
+
+/// Applies transactions to client accounts one at a time, for embedding the engine in-process.
+///
+/// ```
+/// use toy_transaction::transaction_engine::{Engine, TransactionInput};
+///
+/// let mut engine = Engine::new();
+/// engine.apply(TransactionInput::deposit(1, 1, 10.0)).unwrap();
+/// engine.apply(TransactionInput::withdrawal(1, 2, 4.0)).unwrap();
+///
+/// assert_eq!(engine.account(1).unwrap().available(), 6.0);
+/// ```
+#[derive(Debug)]
+pub struct Engine {
+    limits: Limits,
+    policy: Rc<Policy>,
+    rules: Rc<Rules>,
+    listeners: Rc<Listeners>,
+    processors: BTreeMap<u16, TransactionProcessor>,
+    /// Latest timestamp of any transaction applied, the time open disputes are aged to
+    latest: Option<Timestamp>,
+    /// Ids of the deposits, withdrawals and admin adjustments applied to any client, kept
+    /// when the policy rejects duplicates since transaction ids are unique across clients
+    tx_ids: HashSet<u32>,
+    /// Ids of the admin adjustments among them, which are not settled transactions so are
+    /// carried over in snapshots on their own
+    adjustment_ids: BTreeSet<u32>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            limits: Limits::default(),
+            policy: Rc::new(Policy::default()),
+            rules: Rc::new(Rules::new()),
+            listeners: Rc::new(Listeners::new()),
+            processors: BTreeMap::new(),
+            latest: None,
+            tx_ids: HashSet::new(),
+            adjustment_ids: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = Rc::new(policy);
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = Rc::new(rules);
+        self
+    }
+
+    pub fn with_listeners(mut self, listeners: Listeners) -> Self {
+        self.listeners = Rc::new(listeners);
+        self
+    }
+
+    /// Apply a transaction to the client's account, opening the account on first use even
+    /// when the transaction is rejected
+    pub fn apply(&mut self, transaction: TransactionInput) -> Result<Outcome, Rejection> {
+        self.latest = self.latest.max(transaction.timestamp());
+        let (client, tx) = (transaction.client(), transaction.tx());
+        let check = self.check_duplicate(&transaction);
+        let rejects_duplicates = self.rejects_duplicates();
+        let processor = self.processor(client);
+        let outcome = processor.apply_checked(transaction, check);
+        if rejects_duplicates && processor.disputes().find(tx).is_ok() {
+            self.tx_ids.insert(tx);
+        }
+        outcome
+    }
+
+    pub fn apply_admin(&mut self, transaction: &AdminInput) -> Result<Outcome, Rejection> {
+        self.apply_admin_checked(transaction, Ok(()))
+    }
+
+    /// Apply the admin transaction unless `check` rejects it, adjustments may not reuse the
+    /// id of another client transaction when the policy rejects duplicates
+    pub(crate) fn apply_admin_checked(
+        &mut self,
+        transaction: &AdminInput,
+        check: Result<(), Rejection>,
+    ) -> Result<Outcome, Rejection> {
+        let is_adjustment = transaction.action == AdminAction::Adjust;
+        let check = if is_adjustment {
+            check.and(self.check_unused(transaction.tx))
+        } else {
+            check
+        };
+        let rejects_duplicates = self.rejects_duplicates();
+        let outcome = self
+            .processor(transaction.client)
+            .apply_admin_checked(transaction, check);
+        if rejects_duplicates && is_adjustment && outcome.is_ok() {
+            self.tx_ids.insert(transaction.tx);
+            self.adjustment_ids.insert(transaction.tx);
+        }
+        outcome
+    }
+
+    /// Journal entries for the disputes the client's last transaction expired, empty for a
+    /// client without an account
+    pub fn expired(&self, client: u16) -> &[JournalEntry] {
+        self.processors
+            .get(&client)
+            .map_or(&[], TransactionProcessor::expired)
+    }
+
+    /// Ledger postings made by the client's last transaction, including any disputes it
+    /// expired, empty for a client without an account
+    pub fn postings(&self, client: u16) -> &[Posting] {
+        self.processors.get(&client).map_or(&[], |processor| {
+            processor.running_state().ledger().postings()
+        })
+    }
+
+    /// Disputes still open in client and transaction id order, aged to the latest timestamp
+    /// of any transaction applied
+    pub fn open_disputes(&self) -> Vec<OpenDispute> {
+        self.processors
+            .values()
+            .flat_map(|processor| processor.open_disputes(self.latest))
+            .collect()
+    }
+
+    /// Open disputes grouped by how many days they have been open
+    pub fn dispute_aging(&self) -> Vec<AgingBucket> {
+        dispute_aging(&self.open_disputes(), self.policy.precision())
+    }
+
+    /// Balances of every client with a timestamped transaction at or before `as_of`, in client
+    /// order
+    pub fn balances_as_of(&self, as_of: Timestamp) -> Vec<Balance> {
+        self.processors
+            .values()
+            .filter_map(|processor| processor.balance_as_of(as_of))
+            .collect()
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn account(&self, client: u16) -> Option<&TransactionRunningState> {
+        self.processors
+            .get(&client)
+            .map(TransactionProcessor::running_state)
+    }
+
+    /// Every account in client order
+    pub fn accounts(&self) -> impl Iterator<Item = &TransactionRunningState> {
+        self.processors
+            .values()
+            .map(TransactionProcessor::running_state)
+    }
+
+    /// Accounts in negative available or total funds, in client order
+    pub fn negative_balances(&self) -> Vec<Balance> {
+        negative_balances(self.accounts())
+    }
+
+    pub fn trial_balance(&self) -> TrialBalance {
+        self.accounts()
+            .map(|account| account.ledger().trial_balance())
+            .fold(TrialBalance::default(), |total, trial_balance| {
+                total + trial_balance
+            })
+    }
+
+    /// Write the state of every account as a versioned binary snapshot, for a later run to
+    /// carry on from with `load_snapshot`
+    pub fn save_snapshot<W: io::Write>(&self, output: W) -> Result<(), Error> {
+        let snapshot = Snapshot {
+            latest: self.latest,
+            adjustment_ids: self.adjustment_ids.iter().copied().collect(),
+            accounts: self
+                .processors
+                .values()
+                .map(TransactionProcessor::snapshot)
+                .collect(),
+        };
+        snapshot.write(output)
+    }
+
+    /// Carry on from the accounts in a snapshot, the engine's limits, policy, rules and
+    /// listeners apply to them from here on. Fails once any account is open.
+    pub fn load_snapshot<R: io::Read>(&mut self, input: R) -> Result<(), Error> {
+        if !self.processors.is_empty() {
+            return Err("a snapshot can only be loaded before any transactions".into());
+        }
+
+        let snapshot = Snapshot::read(input)?;
+        let rejects_duplicates = self.rejects_duplicates();
+        for account in &snapshot.accounts {
+            let processor = self.processor(account.client);
+            processor.restore(account)?;
+            if rejects_duplicates {
+                let settled = processor.disputes().settled();
+                self.tx_ids.extend(settled.iter().map(|(tx, _)| *tx));
+            }
+        }
+        if rejects_duplicates {
+            self.tx_ids.extend(snapshot.adjustment_ids.iter().copied());
+            self.adjustment_ids.extend(snapshot.adjustment_ids);
+        }
+        self.latest = snapshot.latest;
+        Ok(())
+    }
+
+    /// Check the books balance and return the final accounts in client order
+    pub fn finish(self) -> Result<Vec<TransactionRunningState>, Error> {
+        let trial_balance = self.trial_balance();
+        if !trial_balance.is_balanced() {
+            return Err(format!(
+                "Trial balance failed, debits {} do not equal credits {}",
+                trial_balance.debits, trial_balance.credits
+            )
+            .into());
+        }
+        if let Some(account) = self.accounts().find(|account| !account.matches_ledger()) {
+            return Err(format!(
+                "Client {} balances do not match the ledger, available {} held {} total {}",
+                account.client(),
+                account.available(),
+                account.held(),
+                account.total()
+            )
+            .into());
+        }
+
+        Ok(self
+            .processors
+            .into_values()
+            .map(TransactionProcessor::into_running_state)
+            .collect())
+    }
+
+    fn rejects_duplicates(&self) -> bool {
+        self.policy.input.duplicates == Duplicates::Reject
+    }
+
+    /// Reject a deposit or withdrawal reusing the id of one applied to any client
+    fn check_duplicate(&self, transaction: &TransactionInput) -> Result<(), Rejection> {
+        match transaction.transaction_type() {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                self.check_unused(transaction.tx())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject an id already used by a deposit, withdrawal or admin adjustment of any client
+    fn check_unused(&self, tx: u32) -> Result<(), Rejection> {
+        if self.rejects_duplicates() && self.tx_ids.contains(&tx) {
+            Err(Rejection::DuplicateTransaction)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn processor(&mut self, client: u16) -> &mut TransactionProcessor {
+        let limits = &self.limits;
+        let policy = &self.policy;
+        let rules = &self.rules;
+        let listeners = &self.listeners;
+        self.processors.entry(client).or_insert_with(|| {
+            TransactionProcessor::new(client)
+                .with_limits(limits.for_client(client))
+                .with_policy(Rc::clone(policy))
+                .with_rules(Rc::clone(rules))
+                .with_listeners(Rc::clone(listeners))
+        })
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_engine::Account;
+
+    #[test]
+    fn reject_a_transaction_id_used_by_another_client() {
+        let policy = Policy::from_toml("[input]\nduplicates = \"reject\"").unwrap();
+        let mut engine = Engine::new().with_policy(policy);
+
+        engine.apply(TransactionInput::deposit(1, 1, 10.0)).unwrap();
+        let duplicate = engine.apply(TransactionInput::deposit(2, 1, 5.0));
+        let withdrawal = engine.apply(TransactionInput::withdrawal(2, 1, 5.0));
+
+        assert_eq!(duplicate.unwrap_err(), Rejection::DuplicateTransaction);
+        assert_eq!(withdrawal.unwrap_err(), Rejection::DuplicateTransaction);
+        assert_eq!(engine.account(2).unwrap().total(), 0.0);
+        assert!(Engine::new()
+            .apply(TransactionInput::deposit(2, 1, 5.0))
+            .is_ok());
+    }
+
+    #[test]
+    fn reject_an_admin_adjustment_reusing_a_transaction_id() {
+        let policy = Policy::from_toml("[input]\nduplicates = \"reject\"").unwrap();
+        let mut engine = Engine::new().with_policy(policy);
+        let adjust = |client, tx| AdminInput {
+            action: AdminAction::Adjust,
+            client,
+            tx,
+            amount: Some(2.0),
+            reason: Some("goodwill".to_string()),
+            timestamp: None,
+        };
+
+        engine.apply(TransactionInput::deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(
+            engine.apply_admin(&adjust(2, 1)).unwrap_err(),
+            Rejection::DuplicateTransaction
+        );
+        engine.apply_admin(&adjust(2, 2)).unwrap();
+        assert_eq!(
+            engine
+                .apply(TransactionInput::deposit(1, 2, 5.0))
+                .unwrap_err(),
+            Rejection::DuplicateTransaction
+        );
+
+        let mut snapshot = Vec::new();
+        engine.save_snapshot(&mut snapshot).unwrap();
+        let policy = Policy::from_toml("[input]\nduplicates = \"reject\"").unwrap();
+        let mut restored = Engine::new().with_policy(policy);
+        restored.load_snapshot(snapshot.as_slice()).unwrap();
+        assert_eq!(
+            restored.apply_admin(&adjust(3, 2)).unwrap_err(),
+            Rejection::DuplicateTransaction
+        );
+    }
+
+    #[test]
+    fn fail_to_finish_when_the_books_do_not_balance() {
+        let mut engine = Engine::new();
+        engine.apply(TransactionInput::deposit(1, 1, 10.0)).unwrap();
+        let mut output = Vec::new();
+        engine.save_snapshot(&mut output).unwrap();
+        let mut snapshot = Snapshot::read(output.as_slice()).unwrap();
+        // The client is owed more than the house holds
+        snapshot.accounts[0]
+            .balances
+            .push((Account::ClientHeld, 1.0));
+        let mut output = Vec::new();
+        snapshot.write(&mut output).unwrap();
+
+        let mut engine = Engine::new();
+        engine.load_snapshot(output.as_slice()).unwrap();
+        engine.apply(TransactionInput::deposit(1, 2, 5.0)).unwrap();
+
+        let err = engine.finish().unwrap_err();
+        assert!(err.to_string().starts_with("Trial balance failed"));
+    }
+}
@@ -0,0 +1,92 @@
+use super::{AdminAction, Precision, Rejection, TransactionRunningState, TransactionType};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Client,
+    Admin,
+    /// Synthetic resolves and chargebacks of disputes left open past the policy's expiry
+    Expiry,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum JournalType {
+    Transaction(TransactionType),
+    Admin(AdminAction),
+    Expiry(TransactionType),
+}
+
+/// A record of a transaction being applied or rejected, along with the resulting balances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub client: u16,
+    pub tx: u32,
+    pub source: Source,
+    pub journal_type: JournalType,
+    pub amount: Option<f64>,
+    pub reason: Option<String>,
+    pub rejection: Option<&'static str>,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+    /// Rounding applied to the balances when the entry is written out
+    precision: Precision,
+}
+
+impl JournalEntry {
+    pub fn new(
+        journal_type: JournalType,
+        tx: u32,
+        amount: Option<f64>,
+        result: &Result<(), Rejection>,
+        state: &TransactionRunningState,
+    ) -> Self {
+        let source = match journal_type {
+            JournalType::Transaction(_) => Source::Client,
+            JournalType::Admin(_) => Source::Admin,
+            JournalType::Expiry(_) => Source::Expiry,
+        };
+
+        JournalEntry {
+            client: state.client(),
+            tx,
+            source,
+            journal_type,
+            amount,
+            reason: None,
+            rejection: result.err().map(|rejection| rejection.code()),
+            available: state.available(),
+            held: state.held(),
+            total: state.total(),
+            locked: state.locked(),
+            precision: state.precision(),
+        }
+    }
+
+    pub fn with_reason(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+}
+
+impl Serialize for JournalEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entry = serializer.serialize_struct("JournalEntry", 12)?;
+        entry.serialize_field("client", &self.client)?;
+        entry.serialize_field("tx", &self.tx)?;
+        entry.serialize_field("source", &self.source)?;
+        entry.serialize_field("type", &self.journal_type)?;
+        entry.serialize_field("amount", &self.amount)?;
+        entry.serialize_field("reason", &self.reason)?;
+        entry.serialize_field("rejection", &self.rejection)?;
+        entry.serialize_field("available", &self.precision.round(self.available))?;
+        entry.serialize_field("held", &self.precision.round(self.held))?;
+        entry.serialize_field("total", &self.precision.round(self.total))?;
+        entry.serialize_field("locked", &self.locked)?;
+        entry.end()
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Add;
+
+/// Amounts within this share of the larger one are treated as equal, so the floating point
+/// error accumulated over a long run does not fail the trial balance
+const TOLERANCE: f64 = 1e-9;
+
+/// Snapshots store accounts by their position here, so new accounts go at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Account {
+    ClientAvailable,
+    ClientHeld,
+    HouseCash,
+    ChargebackLoss,
+    /// Provisional credits the house has given for disputed withdrawals
+    DisputeCredit,
+    Adjustments,
+}
+
+impl Account {
+    /// Client accounts are liabilities of the house, so increase with credits
+    fn is_credit_normal(&self) -> bool {
+        matches!(self, Account::ClientAvailable | Account::ClientHeld)
+    }
+}
+
+/// One side of a balanced ledger entry.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Posting {
+    pub client: u16,
+    pub tx: u32,
+    pub account: Account,
+    pub debit: Option<f64>,
+    pub credit: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrialBalance {
+    pub debits: f64,
+    pub credits: f64,
+}
+
+impl TrialBalance {
+    pub fn is_balanced(&self) -> bool {
+        agrees(self.debits, self.credits)
+    }
+}
+
+impl Add for TrialBalance {
+    type Output = TrialBalance;
+
+    fn add(self, other: TrialBalance) -> TrialBalance {
+        TrialBalance {
+            debits: self.debits + other.debits,
+            credits: self.credits + other.credits,
+        }
+    }
+}
+
+/// Double-entry ledger for a single client, every movement of money is posted as a debit
+/// against one account and a credit of the same amount against another. Only the balances
+/// and the postings of the latest transaction are kept, the postings are for the caller to
+/// export as they are made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ledger {
+    client: u16,
+    /// Postings made by the latest transaction
+    postings: Vec<Posting>,
+    balances: HashMap<Account, f64>,
+}
+
+impl Ledger {
+    pub fn new(client: u16) -> Self {
+        Ledger {
+            client,
+            postings: Vec::new(),
+            balances: HashMap::new(),
+        }
+    }
+
+    pub fn post(&mut self, tx: u32, debit: Account, credit: Account, amount: f64) {
+        self.postings.push(Posting {
+            client: self.client,
+            tx,
+            account: debit,
+            debit: Some(amount),
+            credit: None,
+        });
+        self.postings.push(Posting {
+            client: self.client,
+            tx,
+            account: credit,
+            debit: None,
+            credit: Some(amount),
+        });
+
+        *self.balances.entry(debit).or_insert(0.0) += signed(debit, -amount);
+        *self.balances.entry(credit).or_insert(0.0) += signed(credit, amount);
+    }
+
+    /// Balance of the account on its normal side
+    pub fn balance(&self, account: Account) -> f64 {
+        self.balances.get(&account).copied().unwrap_or(0.0)
+    }
+
+    /// Balances of the debit normal accounts against those of the credit normal accounts,
+    /// what the house holds and has lost against what it owes the client
+    pub fn trial_balance(&self) -> TrialBalance {
+        self.balances.iter().fold(
+            TrialBalance::default(),
+            |trial_balance, (account, balance)| {
+                if account.is_credit_normal() {
+                    TrialBalance {
+                        credits: trial_balance.credits + balance,
+                        ..trial_balance
+                    }
+                } else {
+                    TrialBalance {
+                        debits: trial_balance.debits + balance,
+                        ..trial_balance
+                    }
+                }
+            },
+        )
+    }
+
+    /// Postings made by the latest transaction
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// Start a new transaction, dropping the postings of the previous one
+    pub(crate) fn clear_postings(&mut self) {
+        self.postings.clear();
+    }
+
+    /// Balance of every account posted to, in account order
+    pub fn balances(&self) -> Vec<(Account, f64)> {
+        let mut balances: Vec<(Account, f64)> = self
+            .balances
+            .iter()
+            .map(|(account, balance)| (*account, *balance))
+            .collect();
+        balances.sort_by_key(|(account, _)| *account);
+        balances
+    }
+
+    /// Carry the balances over from a snapshot, the postings made before it are not kept
+    pub(crate) fn restore(&mut self, balances: &[(Account, f64)]) {
+        self.postings.clear();
+        self.balances = balances.iter().copied().collect();
+    }
+}
+
+/// The amounts are equal to within the floating point error of the larger one
+pub(crate) fn agrees(left: f64, right: f64) -> bool {
+    (left - right).abs() <= TOLERANCE * left.abs().max(right.abs()).max(1.0)
+}
+
+fn signed(account: Account, credit_amount: f64) -> f64 {
+    if account.is_credit_normal() {
+        credit_amount
+    } else {
+        -credit_amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_balanced_entries() {
+        let mut ledger = Ledger::new(1);
+        ledger.post(1, Account::HouseCash, Account::ClientAvailable, 100.0);
+        ledger.post(2, Account::ClientAvailable, Account::ClientHeld, 40.0);
+
+        assert_eq!(ledger.balance(Account::HouseCash), 100.0);
+        assert_eq!(ledger.balance(Account::ClientAvailable), 60.0);
+        assert_eq!(ledger.balance(Account::ClientHeld), 40.0);
+        assert_eq!(ledger.postings().len(), 4);
+        assert!(ledger.trial_balance().is_balanced());
+
+        // A posting without its other side leaves the house and client sides apart
+        *ledger.balances.get_mut(&Account::HouseCash).unwrap() += 5.0;
+        assert!(!ledger.trial_balance().is_balanced());
+    }
+
+    #[test]
+    fn balance_despite_floating_point_error() {
+        let mut ledger = Ledger::new(1);
+        for tx in 0..1_000 {
+            ledger.post(tx, Account::HouseCash, Account::ClientAvailable, 0.1);
+            ledger.post(tx, Account::ClientAvailable, Account::ClientHeld, 0.07);
+        }
+        ledger.post(1_000, Account::ClientHeld, Account::HouseCash, 70.0);
+
+        let trial_balance = ledger.trial_balance();
+        assert_ne!(trial_balance.debits, trial_balance.credits);
+        assert!(trial_balance.is_balanced());
+    }
+}
@@ -0,0 +1,285 @@
+use super::Rejection;
+use crate::Error;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    MaxDeposit,
+    MaxWithdrawal,
+    WindowVolume,
+    OpenDisputes,
+}
+
+impl Limit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Limit::MaxDeposit => "max_deposit",
+            Limit::MaxWithdrawal => "max_withdrawal",
+            Limit::WindowVolume => "max_window_volume",
+            Limit::OpenDisputes => "max_open_disputes",
+        }
+    }
+}
+
+/// Limits applied to a single client, any limit left unset is not enforced.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClientLimits {
+    pub max_deposit: Option<f64>,
+    pub max_withdrawal: Option<f64>,
+    /// Number of most recent deposits/withdrawals making up the rolling window,
+    /// when unset the window covers every transaction for the client
+    pub window_size: Option<usize>,
+    pub max_window_volume: Option<f64>,
+    pub max_open_disputes: Option<usize>,
+}
+
+impl ClientLimits {
+    fn merge(&self, overrides: &ClientLimits) -> ClientLimits {
+        ClientLimits {
+            max_deposit: overrides.max_deposit.or(self.max_deposit),
+            max_withdrawal: overrides.max_withdrawal.or(self.max_withdrawal),
+            window_size: overrides.window_size.or(self.window_size),
+            max_window_volume: overrides.max_window_volume.or(self.max_window_volume),
+            max_open_disputes: overrides.max_open_disputes.or(self.max_open_disputes),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let amounts = [
+            (Limit::MaxDeposit, self.max_deposit),
+            (Limit::MaxWithdrawal, self.max_withdrawal),
+            (Limit::WindowVolume, self.max_window_volume),
+        ];
+
+        for (limit, amount) in amounts.iter() {
+            if let Some(amount) = amount {
+                if !amount.is_finite() || *amount < 0.0 {
+                    return Err(format!("{} must be zero or more", limit.name()).into());
+                }
+            }
+        }
+
+        if self.window_size == Some(0) {
+            return Err("window_size must be greater than zero".into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientOverride {
+    client: u16,
+    #[serde(flatten)]
+    limits: ClientLimits,
+}
+
+/// Limits configuration, a default set applied to every client plus per client overrides.
+///
+/// ```toml
+/// [default]
+/// max_withdrawal = 1000.0
+///
+/// [[client]]
+/// client = 1
+/// max_withdrawal = 50.0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    #[serde(default)]
+    default: ClientLimits,
+    #[serde(default)]
+    client: Vec<ClientOverride>,
+}
+
+impl Limits {
+    pub fn from_toml(input: &str) -> Result<Self, Error> {
+        let limits: Limits = toml::from_str(input)?;
+
+        limits.default.validate()?;
+        for client_override in &limits.client {
+            client_override.limits.validate()?;
+        }
+
+        Ok(limits)
+    }
+
+    pub fn for_client(&self, client: u16) -> ClientLimits {
+        self.client
+            .iter()
+            .filter(|client_override| client_override.client == client)
+            .fold(self.default.clone(), |limits, client_override| {
+                limits.merge(&client_override.limits)
+            })
+    }
+}
+
+/// Tracks the client activity the limits are evaluated against while transactions are applied.
+#[derive(Debug, Clone)]
+pub struct LimitTracker {
+    limits: ClientLimits,
+    /// Amounts in the rolling window, oldest first, only kept when the window has a size
+    window: VecDeque<f64>,
+    /// Sum of the amounts in the rolling window
+    volume: f64,
+}
+
+impl LimitTracker {
+    pub fn new(limits: ClientLimits) -> Self {
+        LimitTracker {
+            limits,
+            window: VecDeque::new(),
+            volume: 0.0,
+        }
+    }
+
+    pub fn check_deposit(&self, amount: f64) -> Result<(), Rejection> {
+        if exceeds(amount, self.limits.max_deposit) {
+            return Err(Rejection::LimitExceeded(Limit::MaxDeposit));
+        }
+        self.check_window(amount)
+    }
+
+    pub fn check_withdrawal(&self, amount: f64) -> Result<(), Rejection> {
+        if exceeds(amount, self.limits.max_withdrawal) {
+            return Err(Rejection::LimitExceeded(Limit::MaxWithdrawal));
+        }
+        self.check_window(amount)
+    }
+
+    pub fn check_dispute(&self, open_disputes: usize) -> Result<(), Rejection> {
+        match self.limits.max_open_disputes {
+            Some(max) if open_disputes >= max => Err(Rejection::LimitExceeded(Limit::OpenDisputes)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Amounts in the rolling window, oldest first, empty when the window covers every
+    /// transaction
+    pub fn window(&self) -> impl Iterator<Item = &f64> {
+        self.window.iter()
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    /// Movements are only tracked when there is a window volume limit to check them against
+    pub fn record_movement(&mut self, amount: f64) {
+        if self.limits.max_window_volume.is_none() {
+            return;
+        }
+
+        self.volume += amount;
+        if let Some(window_size) = self.limits.window_size {
+            self.window.push_back(amount);
+            while self.window.len() > window_size {
+                if let Some(oldest) = self.window.pop_front() {
+                    self.volume -= oldest;
+                }
+            }
+        }
+    }
+
+    /// Carry the window over from a snapshot, trimmed to this tracker's window size
+    pub(crate) fn restore(&mut self, window: &[f64], volume: f64) {
+        if self.limits.window_size.is_some() {
+            window
+                .iter()
+                .for_each(|amount| self.record_movement(*amount));
+        } else if self.limits.max_window_volume.is_some() {
+            self.volume = volume;
+        }
+    }
+
+    fn check_window(&self, amount: f64) -> Result<(), Rejection> {
+        if self.limits.max_window_volume.is_none() {
+            return Ok(());
+        }
+
+        // The candidate transaction pushes the oldest one out of a full window
+        let leaving = match (self.limits.window_size, self.window.front()) {
+            (Some(window_size), Some(oldest)) if self.window.len() >= window_size => *oldest,
+            _ => 0.0,
+        };
+        let volume = self.volume - leaving + amount;
+
+        if exceeds(volume, self.limits.max_window_volume) {
+            Err(Rejection::LimitExceeded(Limit::WindowVolume))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn exceeds(amount: f64, limit: Option<f64>) -> bool {
+    matches!(limit, Some(limit) if amount > limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_overrides_merge_with_default() {
+        let limits = Limits::from_toml(
+            r#"
+            [default]
+            max_withdrawal = 100.0
+            max_open_disputes = 2
+
+            [[client]]
+            client = 1
+            max_withdrawal = 10.0
+            "#,
+        )
+        .unwrap();
+
+        let client_limits = limits.for_client(1);
+        assert_eq!(client_limits.max_withdrawal, Some(10.0));
+        assert_eq!(client_limits.max_open_disputes, Some(2));
+
+        let default_limits = limits.for_client(2);
+        assert_eq!(default_limits.max_withdrawal, Some(100.0));
+    }
+
+    #[test]
+    fn track_the_window_only_when_limited() {
+        let mut untracked = LimitTracker::new(ClientLimits::default());
+        untracked.record_movement(10.0);
+        assert_eq!((untracked.window().count(), untracked.volume()), (0, 0.0));
+
+        let mut tracker = LimitTracker::new(ClientLimits {
+            window_size: Some(2),
+            max_window_volume: Some(10.0),
+            ..ClientLimits::default()
+        });
+        tracker.record_movement(4.0);
+        tracker.record_movement(5.0);
+        tracker.record_movement(3.0);
+
+        assert_eq!(
+            tracker.window().copied().collect::<Vec<f64>>(),
+            vec![5.0, 3.0]
+        );
+        assert_eq!(tracker.volume(), 8.0);
+        assert!(tracker.check_deposit(5.0).is_ok());
+        assert!(tracker.check_deposit(7.5).is_err());
+    }
+
+    #[test]
+    fn reject_negative_limit() {
+        let limits = Limits::from_toml(
+            r#"
+            [default]
+            max_deposit = -1.0
+            "#,
+        );
+
+        assert!(limits.is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use super::{AdminInput, TransactionInput, TransactionRunningState};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use tracing::error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Applied,
+    Rejected,
+    DisputeOpened,
+    DisputeResolved,
+    Chargeback,
+    AccountLocked,
+    /// A dispute left open past the policy's expiry, followed by its resolve or chargeback
+    DisputeExpired,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Applied => "applied",
+            EventKind::Rejected => "rejected",
+            EventKind::DisputeOpened => "dispute_opened",
+            EventKind::DisputeResolved => "dispute_resolved",
+            EventKind::Chargeback => "chargeback",
+            EventKind::AccountLocked => "account_locked",
+            EventKind::DisputeExpired => "dispute_expired",
+        }
+    }
+}
+
+/// The client or admin transaction that caused an event, or the synthetic resolve or
+/// chargeback of an expired dispute.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(untagged)]
+pub enum EventTransaction<'a> {
+    Client(&'a TransactionInput),
+    Admin(&'a AdminInput),
+    Expiry(&'a TransactionInput),
+}
+
+impl EventTransaction<'_> {
+    pub fn tx(&self) -> u32 {
+        match self {
+            EventTransaction::Client(transaction) | EventTransaction::Expiry(transaction) => {
+                transaction.tx()
+            }
+            EventTransaction::Admin(transaction) => transaction.tx,
+        }
+    }
+}
+
+/// Engine activity passed to every listener, `state` is the account after the transaction.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Event<'a> {
+    pub kind: EventKind,
+    pub transaction: EventTransaction<'a>,
+    /// Reason code, only set for rejections
+    pub rejection: Option<&'static str>,
+    pub state: &'a TransactionRunningState,
+}
+
+/// Observer notified of engine activity, a single transaction can raise several events, for
+/// example a chargeback raises `applied`, `chargeback` and `account_locked` in that order.
+pub trait Listener: fmt::Debug {
+    fn on_event(&self, event: &Event);
+}
+
+/// The set of listeners notified of every event.
+#[derive(Debug, Default)]
+pub struct Listeners {
+    listeners: Vec<Box<dyn Listener>>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Listeners {
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn with_listener<L: Listener + 'static>(mut self, listener: L) -> Self {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    pub fn notify(&self, event: &Event) {
+        for listener in &self.listeners {
+            listener.on_event(event);
+        }
+    }
+}
+
+/// Write a line of text for every event.
+pub struct LogListener<W: Write> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> LogListener<W> {
+    pub fn new(writer: W) -> Self {
+        LogListener {
+            writer: RefCell::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Listener for LogListener<W> {
+    fn on_event(&self, event: &Event) {
+        let state = event.state;
+        let result = writeln!(
+            self.writer.borrow_mut(),
+            "{} client={} tx={} rejection={} available={:.4} held={:.4} total={:.4} locked={}",
+            event.kind.name(),
+            state.client(),
+            event.transaction.tx(),
+            event.rejection.unwrap_or("none"),
+            state.available(),
+            state.held(),
+            state.total(),
+            state.locked()
+        );
+        report_error(result);
+    }
+}
+
+/// Write every event as a line of JSON.
+pub struct JsonListener<W: Write> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> JsonListener<W> {
+    pub fn new(writer: W) -> Self {
+        JsonListener {
+            writer: RefCell::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Listener for JsonListener<W> {
+    fn on_event(&self, event: &Event) {
+        let mut writer = self.writer.borrow_mut();
+        let result = serde_json::to_writer(&mut *writer, event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(writer));
+        report_error(result);
+    }
+}
+
+impl<W: Write> fmt::Debug for LogListener<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LogListener").finish()
+    }
+}
+
+impl<W: Write> fmt::Debug for JsonListener<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JsonListener").finish()
+    }
+}
+
+/// Listeners can not fail the transaction, a failed write is reported and processing carries on
+fn report_error(result: io::Result<()>) {
+    if let Err(err) = result {
+        error!(error = %err, "failed to write event");
+    }
+}
@@ -0,0 +1,41 @@
+use super::{Balance, TransactionRunningState};
+
+/// Accounts in negative available or total funds, for collections to follow up.
+pub fn negative_balances<'a, I>(accounts: I) -> Vec<Balance>
+where
+    I: IntoIterator<Item = &'a TransactionRunningState>,
+{
+    accounts
+        .into_iter()
+        .map(Balance::from_account)
+        .filter(Balance::is_negative)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_negative_accounts() {
+        let mut overdrawn = TransactionRunningState::new(1);
+        overdrawn.deposit(1, 10.0).unwrap();
+        overdrawn.withdraw(2, 8.0, false).unwrap();
+        overdrawn.hold(1, 10.0);
+        let mut in_credit = TransactionRunningState::new(2);
+        in_credit.deposit(3, 10.0).unwrap();
+
+        let report = negative_balances(&[overdrawn, in_credit]);
+
+        assert_eq!(
+            report,
+            vec![Balance {
+                client: 1,
+                available: -8.0,
+                held: 10.0,
+                total: 2.0,
+                locked: false,
+            }]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use super::{Moment, Precision, Settled, Timestamp, TransactionRunningState, TransactionType};
+use serde::Serialize;
+
+/// Upper bounds in days of the aging buckets, open disputes older than the last bound fall
+/// in an over bucket
+const AGING_DAYS: [i64; 4] = [7, 30, 60, 90];
+
+/// A dispute still open, with the part of the original transaction disputed and held, at the
+/// output precision.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpenDispute {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub transaction_type: TransactionType,
+    /// Amount of the original transaction
+    pub amount: f64,
+    pub disputed: f64,
+    pub held: f64,
+    /// When the dispute was opened, if the dispute row had a timestamp
+    pub opened: Option<Timestamp>,
+    /// The client's rows received since the dispute was opened
+    pub rows_since: usize,
+    /// Whole days the dispute has been open, when both the dispute and `now` have a timestamp
+    pub age_days: Option<i64>,
+}
+
+impl OpenDispute {
+    /// The dispute as it stands at `now`, the client's latest row
+    pub fn new(account: &TransactionRunningState, tx: u32, settled: &Settled, now: Moment) -> Self {
+        let precision = account.precision();
+        let opened = settled.opened.unwrap_or(now);
+        OpenDispute {
+            client: account.client(),
+            tx,
+            transaction_type: settled.transaction_type,
+            amount: precision.round(settled.amount),
+            disputed: precision.round(settled.disputed),
+            held: precision.round(settled.held),
+            opened: opened.timestamp,
+            rows_since: now.row.saturating_sub(opened.row + 1),
+            age_days: opened
+                .timestamp
+                .zip(now.timestamp)
+                .map(|(opened, now)| (now - opened).num_days()),
+        }
+    }
+}
+
+/// Open disputes of a similar age, with their totals at the output precision.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgingBucket {
+    /// Age range in days, such as `8-30`, `unknown` for disputes without a timestamp
+    pub age: String,
+    pub disputes: u64,
+    pub disputed: f64,
+    pub held: f64,
+}
+
+/// Open disputes grouped by age in days, every bucket is listed even when empty.
+pub fn dispute_aging(disputes: &[OpenDispute], precision: Precision) -> Vec<AgingBucket> {
+    let mut lower = 0;
+    let mut ranges: Vec<(String, Option<(i64, i64)>)> = AGING_DAYS
+        .iter()
+        .map(|upper| {
+            let range = (format!("{}-{}", lower, upper), Some((lower, *upper)));
+            lower = upper + 1;
+            range
+        })
+        .collect();
+    ranges.push((format!("{}+", lower), Some((lower, i64::MAX))));
+    ranges.push(("unknown".to_string(), None));
+
+    ranges
+        .into_iter()
+        .map(|(age, range)| {
+            let in_range = disputes
+                .iter()
+                .filter(|dispute| match (dispute.age_days, range) {
+                    (Some(days), Some((lower, upper))) => lower <= days && days <= upper,
+                    (None, None) => true,
+                    _ => false,
+                });
+            in_range.fold(
+                AgingBucket {
+                    age,
+                    disputes: 0,
+                    disputed: 0.0,
+                    held: 0.0,
+                },
+                |mut bucket, dispute| {
+                    bucket.disputes += 1;
+                    bucket.disputed += dispute.disputed;
+                    bucket.held += dispute.held;
+                    bucket
+                },
+            )
+        })
+        .map(|bucket| AgingBucket {
+            disputed: precision.round(bucket.disputed),
+            held: precision.round(bucket.held),
+            ..bucket
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dispute(age_days: Option<i64>, disputed: f64) -> OpenDispute {
+        OpenDispute {
+            client: 1,
+            tx: 1,
+            transaction_type: TransactionType::Deposit,
+            amount: disputed,
+            disputed,
+            held: disputed,
+            opened: None,
+            rows_since: 0,
+            age_days,
+        }
+    }
+
+    #[test]
+    fn group_open_disputes_by_age() {
+        let aging = dispute_aging(
+            &[
+                dispute(Some(0), 1.0),
+                dispute(Some(7), 2.0),
+                dispute(Some(8), 4.0),
+                dispute(Some(400), 8.0),
+                dispute(None, 16.0),
+            ],
+            Precision::default(),
+        );
+
+        let summary: Vec<(&str, u64, f64)> = aging
+            .iter()
+            .map(|bucket| (bucket.age.as_str(), bucket.disputes, bucket.disputed))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("0-7", 2, 3.0),
+                ("8-30", 1, 4.0),
+                ("31-60", 0, 0.0),
+                ("61-90", 0, 0.0),
+                ("91+", 1, 8.0),
+                ("unknown", 1, 16.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Largest number of decimal places balances can be written with, beyond which f64 can no
+/// longer represent typical balances exactly enough to round meaningfully
+const MAX_PRECISION: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Ties round away from zero, 0.00005 becomes 0.0001
+    HalfAwayFromZero,
+    /// Ties round to the even digit, 0.00005 becomes 0.0000
+    HalfEven,
+    /// Digits past the precision are dropped
+    Truncate,
+}
+
+impl Rounding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rounding::HalfAwayFromZero => "half_away_from_zero",
+            Rounding::HalfEven => "half_even",
+            Rounding::Truncate => "truncate",
+        }
+    }
+}
+
+/// How balances are rounded when written out, amounts are kept unrounded while processing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Precision {
+    pub places: u32,
+    pub rounding: Rounding,
+}
+
+impl Precision {
+    pub fn round(&self, value: f64) -> f64 {
+        let scale = 10_f64.powi(self.places as i32);
+        let scaled = value * scale;
+        let rounded = match self.rounding {
+            Rounding::HalfAwayFromZero => scaled.round(),
+            Rounding::HalfEven => scaled.round_ties_even(),
+            Rounding::Truncate => scaled.trunc(),
+        };
+        rounded / scale
+    }
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision {
+            places: 4,
+            rounding: Rounding::HalfAwayFromZero,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroAmounts {
+    /// Rejected as an invalid amount
+    Reject,
+    /// Applied without changing the balances
+    Ignore,
+}
+
+impl ZeroAmounts {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZeroAmounts::Reject => "reject",
+            ZeroAmounts::Ignore => "ignore",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalDisputes {
+    /// The withdrawn amount is credited to the held funds at the house's expense until the
+    /// dispute is resolved, which reverses the credit, or charged back, which releases it to
+    /// the available funds
+    ProvisionalCredit,
+    /// Balances are left alone while the dispute is open, a chargeback refunds the withdrawn
+    /// amount to the available funds
+    NoHold,
+    /// Withdrawals can not be disputed
+    Reject,
+}
+
+impl WithdrawalDisputes {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WithdrawalDisputes::ProvisionalCredit => "provisional_credit",
+            WithdrawalDisputes::NoHold => "no_hold",
+            WithdrawalDisputes::Reject => "reject",
+        }
+    }
+}
+
+/// Disputes of deposits the client has since spent, so more is disputed than is available.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpentDeposits {
+    /// The full amount is held, leaving available negative and raising a `negative_balance`
+    /// alert
+    Allow,
+    /// Only the available funds are held, the house absorbs the rest on a chargeback
+    Cap,
+    /// Rejected as insufficient funds
+    Reject,
+}
+
+impl SpentDeposits {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpentDeposits::Allow => "allow",
+            SpentDeposits::Cap => "cap",
+            SpentDeposits::Reject => "reject",
+        }
+    }
+}
+
+/// What happens to a dispute left open past the policy's expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryAction {
+    /// The dispute is resolved and the original transaction stands
+    Resolve,
+    /// The dispute is charged back
+    Chargeback,
+}
+
+impl ExpiryAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpiryAction::Resolve => "resolve",
+            ExpiryAction::Chargeback => "chargeback",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Duplicates {
+    /// Applied again, disputes act on the first transaction with the id
+    Allow,
+    /// Rejected when the id is already used by an applied deposit, withdrawal or admin
+    /// adjustment of any client, a processor used on its own only sees its client's
+    Reject,
+}
+
+impl Duplicates {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Duplicates::Allow => "allow",
+            Duplicates::Reject => "reject",
+        }
+    }
+}
+
+/// How rows are ordered relative to their timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOrder {
+    /// Rows are applied in file order, timestamps are optional and not checked
+    File,
+    /// Every row needs a timestamp no earlier than the client's previous row, otherwise it is
+    /// rejected as `missing_timestamp` or `out_of_order`
+    Validate,
+    /// Rows are read in full and sorted by timestamp before being applied, rows with the same
+    /// timestamp keep their file order. Rows without a timestamp are rejected.
+    Sort,
+}
+
+impl RowOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RowOrder::File => "file",
+            RowOrder::Validate => "validate",
+            RowOrder::Sort => "sort",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AmountPolicy {
+    /// Decimal places balances are written with
+    pub precision: u32,
+    pub rounding: Rounding,
+    /// Deposits and withdrawals of zero
+    pub zero: ZeroAmounts,
+    /// Withdrawals of more than the available funds are applied, leaving available negative
+    pub allow_overdraft: bool,
+}
+
+impl Default for AmountPolicy {
+    fn default() -> Self {
+        let precision = Precision::default();
+        AmountPolicy {
+            precision: precision.places,
+            rounding: precision.rounding,
+            zero: ZeroAmounts::Reject,
+            allow_overdraft: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockingPolicy {
+    /// A chargeback locks the account
+    pub lock_on_chargeback: bool,
+    /// Locked accounts reject deposits and withdrawals, by default they still accept them
+    pub reject_when_locked: bool,
+}
+
+impl Default for LockingPolicy {
+    fn default() -> Self {
+        LockingPolicy {
+            lock_on_chargeback: true,
+            reject_when_locked: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    pub withdrawals: WithdrawalDisputes,
+    pub spent_deposits: SpentDeposits,
+    /// Disputes raised more than this many days after the disputed transaction are rejected,
+    /// only checked when both rows have a timestamp
+    pub window_days: Option<u32>,
+    /// A dispute left open through this many more of the client's rows expires before the
+    /// next row is applied
+    pub expire_after_transactions: Option<u32>,
+    /// A dispute left open for more than this many days expires before the client's next row
+    /// is applied, only checked when both rows have a timestamp
+    pub expire_after_days: Option<u32>,
+    pub on_expiry: ExpiryAction,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        DisputePolicy {
+            withdrawals: WithdrawalDisputes::ProvisionalCredit,
+            spent_deposits: SpentDeposits::Allow,
+            window_days: None,
+            expire_after_transactions: None,
+            expire_after_days: None,
+            on_expiry: ExpiryAction::Resolve,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputPolicy {
+    pub duplicates: Duplicates,
+    /// Rows that can not be parsed fail the run, otherwise they are logged and skipped
+    pub strict: bool,
+    pub ordering: RowOrder,
+}
+
+impl Default for InputPolicy {
+    fn default() -> Self {
+        InputPolicy {
+            duplicates: Duplicates::Allow,
+            strict: true,
+            ordering: RowOrder::File,
+        }
+    }
+}
+
+/// Engine behaviour that differs between deployments, every setting is optional and
+/// defaults to the behaviour of the engine without a policy file.
+///
+/// ```toml
+/// [amounts]
+/// precision = 2
+/// rounding = "half_even"
+/// zero = "ignore"
+/// allow_overdraft = false
+///
+/// [locking]
+/// lock_on_chargeback = true
+/// reject_when_locked = true
+///
+/// [disputes]
+/// withdrawals = "reject"
+/// spent_deposits = "cap"
+/// window_days = 120
+/// expire_after_days = 60
+/// on_expiry = "chargeback"
+///
+/// [input]
+/// duplicates = "reject"
+/// strict = false
+/// ordering = "validate"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub amounts: AmountPolicy,
+    pub locking: LockingPolicy,
+    pub disputes: DisputePolicy,
+    pub input: InputPolicy,
+}
+
+impl Policy {
+    pub fn from_toml(input: &str) -> Result<Self, Error> {
+        let policy: Policy = toml::from_str(input)?;
+
+        if policy.amounts.precision > MAX_PRECISION {
+            return Err(format!("precision must be at most {}", MAX_PRECISION).into());
+        }
+        let disputes = &policy.disputes;
+        for (name, days) in [
+            ("window_days", disputes.window_days),
+            (
+                "expire_after_transactions",
+                disputes.expire_after_transactions,
+            ),
+            ("expire_after_days", disputes.expire_after_days),
+        ]
+        .iter()
+        {
+            if *days == Some(0) {
+                return Err(format!("dispute {} must be at least 1", name).into());
+            }
+        }
+
+        Ok(policy)
+    }
+
+    pub fn precision(&self) -> Precision {
+        Precision {
+            places: self.amounts.precision,
+            rounding: self.amounts.rounding,
+        }
+    }
+}
+
+/// One line summary of the active policy for the run summary
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "precision={} rounding={} zero={} allow_overdraft={} lock_on_chargeback={} \
+             reject_when_locked={} withdrawal_disputes={} spent_deposits={} \
+             dispute_window_days={} expire_after_transactions={} expire_after_days={} \
+             on_expiry={} duplicates={} strict={} ordering={}",
+            self.amounts.precision,
+            self.amounts.rounding.name(),
+            self.amounts.zero.name(),
+            self.amounts.allow_overdraft,
+            self.locking.lock_on_chargeback,
+            self.locking.reject_when_locked,
+            self.disputes.withdrawals.name(),
+            self.disputes.spent_deposits.name(),
+            optional_setting(self.disputes.window_days),
+            optional_setting(self.disputes.expire_after_transactions),
+            optional_setting(self.disputes.expire_after_days),
+            self.disputes.on_expiry.name(),
+            self.input.duplicates.name(),
+            self.input.strict,
+            self.input.ordering.name(),
+        )
+    }
+}
+
+/// An optional setting as written in summaries, `none` when unset
+pub(crate) fn optional_setting(value: Option<u32>) -> String {
+    value.map_or_else(|| "none".to_string(), |value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_settings_keep_defaults() {
+        let policy = Policy::from_toml(
+            r#"
+            [amounts]
+            precision = 2
+
+            [input]
+            strict = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.amounts.precision, 2);
+        assert_eq!(policy.amounts.rounding, Rounding::HalfAwayFromZero);
+        assert!(policy.locking.lock_on_chargeback);
+        assert!(!policy.input.strict);
+    }
+
+    #[test]
+    fn reject_invalid_policy() {
+        assert!(Policy::from_toml("[amounts]\nprecision = 12").is_err());
+        assert!(Policy::from_toml("[amounts]\nrounding = \"up\"").is_err());
+        assert!(Policy::from_toml("[locking]\nlock_on_dispute = true").is_err());
+        assert!(Policy::from_toml("[disputes]\nwindow_days = 0").is_err());
+        assert!(Policy::from_toml("[disputes]\nexpire_after_transactions = 0").is_err());
+        assert!(Policy::from_toml("[input]\nordering = \"random\"").is_err());
+    }
+
+    #[test]
+    fn round_by_mode() {
+        let round = |rounding, value| {
+            Precision {
+                places: 2,
+                rounding,
+            }
+            .round(value)
+        };
+
+        assert_eq!(round(Rounding::HalfAwayFromZero, 0.125), 0.13);
+        assert_eq!(round(Rounding::HalfEven, 0.125), 0.12);
+        assert_eq!(round(Rounding::Truncate, 0.129), 0.12);
+        assert_eq!(round(Rounding::Truncate, -0.129), -0.12);
+    }
+}
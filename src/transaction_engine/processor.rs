@@ -1,463 +1,1720 @@
+use super::AccountSnapshot;
+use super::AdminAction;
+use super::AdminInput;
+use super::Alert;
+use super::Balance;
+use super::ClientLimits;
+use super::DisputePolicy;
+use super::DisputeStatus;
+use super::Disputes;
+use super::Duplicates;
+use super::Event;
+use super::EventKind;
+use super::EventTransaction;
+use super::ExpiryAction;
+use super::History;
+use super::JournalEntry;
+use super::JournalType;
+use super::LimitTracker;
+use super::Listeners;
+use super::LockingPolicy;
+use super::Moment;
+use super::OpenDispute;
+use super::Policy;
+use super::Rejection;
+use super::RowOrder;
+use super::Rules;
+use super::Settled;
+use super::SettledSnapshot;
+use super::SpentDeposits;
+use super::Time;
+use super::Timestamp;
 use super::TransactionInput;
 use super::TransactionRunningState;
+use super::TransactionSnapshot;
 use super::TransactionType;
+use super::WithdrawalDisputes;
+use super::ZeroAmounts;
+use crate::Error;
+use chrono::Duration;
+use std::rc::Rc;
+use tracing::{debug, info, warn};
 
-#[derive(Debug)]
+/// Applies a single client's transactions to their account as they arrive.
+#[derive(Debug, Clone)]
 pub struct TransactionProcessor {
-    pub client: u16,
-    transactions: Vec<TransactionInput>,
+    client: u16,
+    policy: Rc<Policy>,
+    rules: Rc<Rules>,
+    listeners: Rc<Listeners>,
+    running_state: TransactionRunningState,
+    limit_tracker: LimitTracker,
+    disputes: Disputes,
+    /// The client's applied transactions, the history the rules are evaluated against
+    history: History,
+    /// Number of rows received for the client, applied or not, the age of disputes in rows
+    rows: usize,
+    /// Timestamp of the client's latest row, the earliest the next row may have when the
+    /// policy orders rows by timestamp
+    last_timestamp: Option<Timestamp>,
+    /// Balances after each applied transaction that had a timestamp, for as-of queries
+    timeline: Vec<(Timestamp, Balance)>,
+    /// Journal entries for the disputes expired by the last transaction
+    expired: Vec<JournalEntry>,
+}
+
+/// The result of an applied transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// Amount moved, the disputed amount for disputes, resolves and chargebacks
+    pub amount: f64,
+    /// Journal entry holding the balances after the transaction was applied
+    pub journal: JournalEntry,
+    /// Alerts raised by the rules, any of which may have locked the account
+    pub alerts: Vec<Alert>,
 }
 
 impl TransactionProcessor {
     pub fn new(client: u16) -> Self {
         TransactionProcessor {
             client,
-            transactions: Vec::new(),
+            policy: Rc::new(Policy::default()),
+            rules: Rc::new(Rules::new()),
+            listeners: Rc::new(Listeners::new()),
+            running_state: TransactionRunningState::new(client),
+            limit_tracker: LimitTracker::new(ClientLimits::default()),
+            disputes: Disputes::new(),
+            history: History::new(0),
+            rows: 0,
+            last_timestamp: None,
+            timeline: Vec::new(),
+            expired: Vec::new(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ClientLimits) -> Self {
+        self.limit_tracker = LimitTracker::new(limits);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: Rc<Policy>) -> Self {
+        self.running_state.set_precision(policy.precision());
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Rc<Rules>) -> Self {
+        self.history = History::new(rules.lookback());
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_listeners(mut self, listeners: Rc<Listeners>) -> Self {
+        self.listeners = listeners;
+        self
+    }
+
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    pub fn running_state(&self) -> &TransactionRunningState {
+        &self.running_state
+    }
+
+    pub fn disputes(&self) -> &Disputes {
+        &self.disputes
+    }
+
+    pub fn into_running_state(self) -> TransactionRunningState {
+        self.running_state
+    }
+
+    /// Balances after the last applied transaction timestamped at or before `as_of`, `None`
+    /// when there is no such transaction. Rows are taken in the order they were applied, so
+    /// the answer is only meaningful when the rows are ordered by timestamp. Rows applied before
+    /// a snapshot was loaded are not covered.
+    pub fn balance_as_of(&self, as_of: Timestamp) -> Option<Balance> {
+        self.timeline
+            .iter()
+            .rev()
+            .find(|(timestamp, _)| *timestamp <= as_of)
+            .map(|(_, balance)| balance.clone())
+    }
+
+    /// The client's open disputes in transaction id order, aged as of `now` and the client's
+    /// latest row
+    pub fn open_disputes(&self, now: Option<Timestamp>) -> Vec<OpenDispute> {
+        let now = Moment {
+            row: self.rows,
+            timestamp: now,
+        };
+        self.disputes
+            .open_disputes()
+            .iter()
+            .map(|(tx, settled)| OpenDispute::new(&self.running_state, *tx, settled, now))
+            .collect()
+    }
+
+    /// Journal entries for the disputes expired by the last transaction, applied before it
+    pub fn expired(&self) -> &[JournalEntry] {
+        &self.expired
+    }
+
+    /// Everything tracked for the client, to be carried over to a later run
+    pub(super) fn snapshot(&self) -> AccountSnapshot {
+        AccountSnapshot {
+            client: self.client,
+            locked: self.running_state.locked(),
+            balances: self.running_state.ledger().balances(),
+            window: self.limit_tracker.window().copied().collect(),
+            volume: self.limit_tracker.volume(),
+            settled: self
+                .disputes
+                .settled()
+                .iter()
+                .map(|(tx, settled)| SettledSnapshot::new(*tx, settled))
+                .collect(),
+            counts: *self.history.counts(),
+            recent: self
+                .history
+                .recent()
+                .iter()
+                .map(TransactionSnapshot::new)
+                .collect(),
+            rows: self.rows as u64,
+            last_timestamp: self.last_timestamp.map(Time::from),
         }
     }
 
-    pub fn add_transaction(&mut self, transaction: TransactionInput) {
-        self.transactions.push(transaction);
+    /// Carry on from a snapshot of the client, the processor's own limits and policy apply
+    /// from here on
+    pub(super) fn restore(&mut self, snapshot: &AccountSnapshot) -> Result<(), Error> {
+        self.running_state
+            .restore(&snapshot.balances, snapshot.locked);
+        self.limit_tracker
+            .restore(&snapshot.window, snapshot.volume);
+        for settled in &snapshot.settled {
+            let (tx, settled) = settled.to_settled()?;
+            self.disputes.restore(tx, settled);
+        }
+        let recent = snapshot
+            .recent
+            .iter()
+            .map(|transaction| transaction.to_transaction(self.client))
+            .collect::<Result<_, _>>()?;
+        self.history.restore(snapshot.counts, recent);
+        self.rows = snapshot.rows as usize;
+        self.last_timestamp = snapshot
+            .last_timestamp
+            .map(Time::to_timestamp)
+            .transpose()?;
+        Ok(())
+    }
+
+    /// Apply the transaction, first expiring any disputes left open past the policy's expiry
+    pub fn apply(&mut self, transaction: TransactionInput) -> Result<Outcome, Rejection> {
+        self.apply_checked(transaction, Ok(()))
+    }
+
+    /// Apply the transaction unless `check` rejects it, for checks made across every client
+    pub(super) fn apply_checked(
+        &mut self,
+        transaction: TransactionInput,
+        check: Result<(), Rejection>,
+    ) -> Result<Outcome, Rejection> {
+        let order = check_order(
+            self.policy.input.ordering,
+            &transaction,
+            &mut self.last_timestamp,
+        );
+        self.expired.clear();
+        self.running_state.clear_postings();
+        if order.is_ok() {
+            self.expire_disputes(&transaction);
+        }
+        let was_locked = self.running_state.locked();
+        let was_negative = self.running_state.available() < 0.0;
+        let result = order
+            .and(check)
+            .and_then(|()| self.process_transaction(&transaction));
+        let status = result.map(|_| ());
+        let outcome = result.map(|amount| {
+            let mut alerts =
+                self.rules
+                    .evaluate(&transaction, &mut self.running_state, &self.history);
+            self.history.record(&transaction);
+            if !was_negative && self.running_state.available() < 0.0 {
+                alerts.push(negative_balance_alert(&transaction, &self.running_state));
+            }
+            if let Some(timestamp) = transaction.timestamp() {
+                self.timeline
+                    .push((timestamp, Balance::from_account(&self.running_state)));
+            }
+            Outcome {
+                amount,
+                journal: JournalEntry::new(
+                    JournalType::Transaction(transaction.transaction_type()),
+                    transaction.tx(),
+                    transaction.amount(),
+                    &status,
+                    &self.running_state,
+                ),
+                alerts,
+            }
+        });
+        match status {
+            Ok(()) => debug!(
+                client = self.client,
+                tx = transaction.tx(),
+                r#type = transaction.transaction_type().name(),
+                "transaction applied"
+            ),
+            Err(rejection) => info!(
+                client = self.client,
+                tx = transaction.tx(),
+                r#type = transaction.transaction_type().name(),
+                reason = rejection.code(),
+                "transaction rejected"
+            ),
+        }
+        self.notify(EventTransaction::Client(&transaction), status, was_locked);
+        self.rows += 1;
+        outcome
     }
 
-    pub fn process_transactions(&self) -> TransactionRunningState {
-        let mut running_state = TransactionRunningState::new(self.client);
+    pub fn apply_admin(&mut self, transaction: &AdminInput) -> Result<Outcome, Rejection> {
+        self.apply_admin_checked(transaction, Ok(()))
+    }
 
-        for transaction in &self.transactions {
-            self.process_transaction(&transaction, &mut running_state)
+    /// Apply the admin transaction unless `check` rejects it, for checks made across every
+    /// client or the admin input as a whole
+    pub(super) fn apply_admin_checked(
+        &mut self,
+        transaction: &AdminInput,
+        check: Result<(), Rejection>,
+    ) -> Result<Outcome, Rejection> {
+        self.running_state.clear_postings();
+        let was_locked = self.running_state.locked();
+        let result = check.and_then(|()| self.process_admin_transaction(transaction));
+        match result {
+            Ok(_) => info!(
+                client = self.client,
+                tx = transaction.tx,
+                r#type = transaction.action.name(),
+                admin_reason = transaction.reason.as_deref().unwrap_or(""),
+                "admin transaction applied"
+            ),
+            Err(rejection) => warn!(
+                client = self.client,
+                tx = transaction.tx,
+                r#type = transaction.action.name(),
+                reason = rejection.code(),
+                "admin transaction rejected"
+            ),
         }
+        self.notify(
+            EventTransaction::Admin(transaction),
+            result.map(|_| ()),
+            was_locked,
+        );
+        let amount = result?;
 
-        running_state
+        Ok(Outcome {
+            amount,
+            journal: JournalEntry::new(
+                JournalType::Admin(transaction.action),
+                transaction.tx,
+                transaction.amount,
+                &Ok(()),
+                &self.running_state,
+            )
+            .with_reason(transaction.reason.clone()),
+            alerts: Vec::new(),
+        })
     }
 
-    fn process_transaction(
+    /// Notify the listeners of a rejection, or of the transaction being applied followed by
+    /// any dispute activity and the account becoming locked
+    fn notify(
         &self,
-        transaction: &TransactionInput,
-        transaction_state: &mut TransactionRunningState,
+        transaction: EventTransaction,
+        result: Result<(), Rejection>,
+        was_locked: bool,
     ) {
-        match &transaction.transaction_type {
+        let event = |kind, rejection| Event {
+            kind,
+            transaction,
+            rejection,
+            state: &self.running_state,
+        };
+
+        if let Err(rejection) = result {
+            self.listeners
+                .notify(&event(EventKind::Rejected, Some(rejection.code())));
+            return;
+        }
+
+        let dispute_kind = |transaction: &TransactionInput| match transaction.transaction_type() {
+            TransactionType::Dispute => Some(EventKind::DisputeOpened),
+            TransactionType::Resolve => Some(EventKind::DisputeResolved),
+            TransactionType::Chargeback => Some(EventKind::Chargeback),
+            _ => None,
+        };
+        let (first_kind, dispute_kind) = match transaction {
+            EventTransaction::Client(transaction) => {
+                (EventKind::Applied, dispute_kind(transaction))
+            }
+            EventTransaction::Admin(_) => (EventKind::Applied, None),
+            EventTransaction::Expiry(transaction) => {
+                (EventKind::DisputeExpired, dispute_kind(transaction))
+            }
+        };
+        let locked_kind = if !was_locked && self.running_state.locked() {
+            Some(EventKind::AccountLocked)
+        } else {
+            None
+        };
+
+        for kind in [Some(first_kind), dispute_kind, locked_kind]
+            .iter()
+            .flatten()
+        {
+            self.listeners.notify(&event(*kind, None));
+        }
+    }
+
+    /// Resolve or charge back, as the policy says, every open dispute that has expired by the
+    /// time of the transaction
+    fn expire_disputes(&mut self, transaction: &TransactionInput) {
+        let now = Moment {
+            row: self.rows,
+            timestamp: transaction.timestamp(),
+        };
+        let expired: Vec<u32> = self
+            .disputes
+            .open_disputes()
+            .iter()
+            .filter(|(_, settled)| {
+                settled
+                    .opened
+                    .is_some_and(|opened| is_expired(&self.policy.disputes, opened, now))
+            })
+            .map(|(tx, _)| *tx)
+            .collect();
+        let transaction_type = match self.policy.disputes.on_expiry {
+            ExpiryAction::Resolve => TransactionType::Resolve,
+            ExpiryAction::Chargeback => TransactionType::Chargeback,
+        };
+
+        for tx in expired {
+            let mut expiry = TransactionInput::new(transaction_type, self.client, tx, None);
+            if let Some(timestamp) = transaction.timestamp() {
+                expiry = expiry.with_timestamp(timestamp);
+            }
+            let was_locked = self.running_state.locked();
+            let result = self.process_transaction(&expiry);
+            let status = result.map(|_| ());
+            info!(
+                client = self.client,
+                tx,
+                r#type = transaction_type.name(),
+                "dispute expired"
+            );
+            self.expired.push(JournalEntry::new(
+                JournalType::Expiry(transaction_type),
+                tx,
+                result.ok(),
+                &status,
+                &self.running_state,
+            ));
+            self.notify(EventTransaction::Expiry(&expiry), status, was_locked);
+        }
+    }
+
+    /// Apply the transaction, returning the amount moved
+    fn process_transaction(&mut self, transaction: &TransactionInput) -> Result<f64, Rejection> {
+        let now = Moment {
+            row: self.rows,
+            timestamp: transaction.timestamp(),
+        };
+        let policy = &self.policy;
+        let transaction_state = &mut self.running_state;
+        let limit_tracker = &mut self.limit_tracker;
+        let disputes = &mut self.disputes;
+        let tx = transaction.tx();
+
+        let amount = match transaction.transaction_type() {
             TransactionType::Deposit => {
-                if let Some(amount) = transaction.amount {
-                    transaction_state.deposit(amount);
+                let amount = transaction.amount().ok_or(Rejection::InvalidAmount)?;
+                check_unlocked(transaction_state, &policy.locking)?;
+                check_duplicate(disputes, policy, tx)?;
+                if is_ignored_zero(policy, amount) {
+                    return Ok(0.0);
                 }
+                limit_tracker.check_deposit(amount)?;
+                transaction_state.deposit(tx, amount)?;
+                limit_tracker.record_movement(amount);
+                disputes.settle(
+                    tx,
+                    TransactionType::Deposit,
+                    amount,
+                    transaction.timestamp(),
+                );
+                amount
             }
             TransactionType::Withdrawal => {
-                if let Some(amount) = transaction.amount {
-                    transaction_state.withdraw(amount);
+                let amount = transaction.amount().ok_or(Rejection::InvalidAmount)?;
+                check_unlocked(transaction_state, &policy.locking)?;
+                check_duplicate(disputes, policy, tx)?;
+                if is_ignored_zero(policy, amount) {
+                    return Ok(0.0);
                 }
+                limit_tracker.check_withdrawal(amount)?;
+                transaction_state.withdraw(tx, amount, policy.amounts.allow_overdraft)?;
+                limit_tracker.record_movement(amount);
+                disputes.settle(
+                    tx,
+                    TransactionType::Withdrawal,
+                    amount,
+                    transaction.timestamp(),
+                );
+                amount
             }
             TransactionType::Dispute => {
-                if let Some(existing_transaction) = self.find_transaction(transaction.tx) {
-                    transaction_state.hold(existing_transaction.amount.unwrap())
+                let (settled, amount) = disputes.check_can_open(tx, transaction.amount())?;
+                if settled.transaction_type == TransactionType::Withdrawal
+                    && policy.disputes.withdrawals == WithdrawalDisputes::Reject
+                {
+                    return Err(Rejection::NotDisputable);
+                }
+                check_dispute_window(policy.disputes.window_days, &settled, transaction)?;
+                // Disputing more of a transaction already under dispute opens no new dispute
+                if settled.dispute != Some(DisputeStatus::Open) {
+                    limit_tracker.check_dispute(disputes.open_count())?;
                 }
+                let held = match settled.transaction_type {
+                    TransactionType::Withdrawal => open_withdrawal_dispute(
+                        transaction_state,
+                        policy.disputes.withdrawals,
+                        tx,
+                        amount,
+                    ),
+                    _ => hold_deposit(
+                        transaction_state,
+                        policy.disputes.spent_deposits,
+                        tx,
+                        amount,
+                    )?,
+                };
+                disputes.open(tx, amount, held, now);
+                amount
             }
             TransactionType::Resolve => {
-                if let Some(existing_transaction) = self.find_transaction(transaction.tx) {
-                    transaction_state.release(existing_transaction.amount.unwrap())
+                let (settled, amount) = disputes.check_is_open(tx, transaction.amount())?;
+                // The held amount never exceeds what is left under dispute
+                let released = settled.held - settled.held.min(settled.disputed - amount);
+                if released > 0.0 {
+                    match settled.transaction_type {
+                        TransactionType::Withdrawal => {
+                            transaction_state.reverse_provisional_credit(tx, released)
+                        }
+                        _ => transaction_state.release(tx, released),
+                    }
                 }
+                disputes.resolve(tx, amount, released);
+                amount
             }
             TransactionType::Chargeback => {
-                if let Some(existing_transaction) = self.find_transaction(transaction.tx) {
-                    match existing_transaction.transaction_type {
-                        TransactionType::Deposit => transaction_state
-                            .chargeback_deposit(existing_transaction.amount.unwrap()),
-                        TransactionType::Withdrawal => transaction_state
-                            .chargeback_withdrawal(existing_transaction.amount.unwrap()),
-                        _ => eprintln!("Tried to charge back an invalid transaction type"),
+                let (settled, amount) = disputes.check_is_open(tx, transaction.amount())?;
+                // The held amount is charged back first, the house pays for any shortfall
+                let used = settled.held.min(amount);
+                match settled.transaction_type {
+                    TransactionType::Withdrawal => {
+                        charge_back_withdrawal(transaction_state, tx, used, amount)
                     }
+                    _ => transaction_state.chargeback_deposit(tx, used, amount),
+                }
+                if policy.locking.lock_on_chargeback {
+                    transaction_state.lock();
+                }
+                disputes.charge_back(tx, amount, used);
+                amount
+            }
+        };
+
+        Ok(amount)
+    }
+
+    fn process_admin_transaction(&mut self, transaction: &AdminInput) -> Result<f64, Rejection> {
+        let transaction_state = &mut self.running_state;
+
+        let amount = match transaction.action {
+            AdminAction::Unlock => {
+                transaction_state.unlock();
+                0.0
+            }
+            AdminAction::Lock => {
+                transaction_state.lock();
+                0.0
+            }
+            AdminAction::Adjust => {
+                let amount = transaction.amount.ok_or(Rejection::InvalidAmount)?;
+                if transaction.reason.is_none() {
+                    return Err(Rejection::MissingReason);
                 }
+                transaction_state.adjust(transaction.tx, amount)?;
+                amount
             }
+        };
+
+        Ok(amount)
+    }
+}
+
+/// Rows must carry a timestamp no earlier than the client's previous row unless the policy
+/// applies rows in file order. Rows that pass move the client's clock forward, even when they
+/// are rejected for another reason.
+fn check_order(
+    ordering: RowOrder,
+    transaction: &TransactionInput,
+    last_timestamp: &mut Option<Timestamp>,
+) -> Result<(), Rejection> {
+    if ordering == RowOrder::File {
+        return Ok(());
+    }
+
+    let timestamp = transaction.timestamp().ok_or(Rejection::MissingTimestamp)?;
+    if last_timestamp.is_some_and(|last| timestamp < last) {
+        return Err(Rejection::OutOfOrder);
+    }
+    *last_timestamp = Some(timestamp);
+    Ok(())
+}
+
+/// Disputes must be raised within the policy's window of the disputed transaction, rows
+/// without a timestamp are never out of the window
+fn check_dispute_window(
+    window_days: Option<u32>,
+    settled: &Settled,
+    transaction: &TransactionInput,
+) -> Result<(), Rejection> {
+    match (window_days, settled.timestamp, transaction.timestamp()) {
+        (Some(days), Some(settled_at), Some(disputed_at))
+            if disputed_at - settled_at > Duration::days(i64::from(days)) =>
+        {
+            Err(Rejection::DisputeWindowExpired)
         }
+        _ => Ok(()),
     }
+}
 
-    fn find_transaction(&self, tx: u32) -> Option<&TransactionInput> {
-        self.transactions
-            .iter()
-            .filter(|transaction| {
-                TransactionType::Deposit == transaction.transaction_type
-                    || TransactionType::Withdrawal == transaction.transaction_type
-            })
-            .find(|transaction| transaction.tx == tx)
+/// Disputes expire once left open through more of the client's rows, or for more days, than
+/// the policy allows
+fn is_expired(policy: &DisputePolicy, opened: Moment, now: Moment) -> bool {
+    let by_rows = policy
+        .expire_after_transactions
+        .is_some_and(|rows| now.row - opened.row > rows as usize);
+    let by_days = match (policy.expire_after_days, opened.timestamp, now.timestamp) {
+        (Some(days), Some(opened_at), Some(now)) => {
+            now - opened_at > Duration::days(i64::from(days))
+        }
+        _ => false,
+    };
+    by_rows || by_days
+}
+
+/// Locked accounts accept no new deposits or withdrawals unless the policy allows them,
+/// disputes of earlier transactions can still be raised and settled
+fn check_unlocked(
+    transaction_state: &TransactionRunningState,
+    locking: &LockingPolicy,
+) -> Result<(), Rejection> {
+    if transaction_state.locked() && locking.reject_when_locked {
+        Err(Rejection::AccountLocked)
+    } else {
+        Ok(())
+    }
+}
+
+/// Hold the disputed deposit, returning the amount held, the policy decides how much of a
+/// deposit the client has since spent is held
+fn hold_deposit(
+    transaction_state: &mut TransactionRunningState,
+    spent_deposits: SpentDeposits,
+    tx: u32,
+    amount: f64,
+) -> Result<f64, Rejection> {
+    let available = transaction_state.available().max(0.0);
+    let held = match spent_deposits {
+        SpentDeposits::Allow => amount,
+        SpentDeposits::Cap => amount.min(available),
+        SpentDeposits::Reject if amount > available => return Err(Rejection::InsufficientFunds),
+        SpentDeposits::Reject => amount,
+    };
+
+    if held > 0.0 {
+        transaction_state.hold(tx, held);
+    }
+    Ok(held)
+}
+
+/// The withdrawn money has already left the account, so unlike a deposit dispute nothing is
+/// taken from the available funds, returning the amount provisionally credited
+fn open_withdrawal_dispute(
+    transaction_state: &mut TransactionRunningState,
+    model: WithdrawalDisputes,
+    tx: u32,
+    amount: f64,
+) -> f64 {
+    match model {
+        WithdrawalDisputes::ProvisionalCredit => {
+            transaction_state.credit_provisionally(tx, amount);
+            amount
+        }
+        WithdrawalDisputes::NoHold | WithdrawalDisputes::Reject => 0.0,
+    }
+}
+
+/// The withdrawal is reversed, the provisionally credited part is released and the rest is
+/// refunded, either way the amount ends up back in the available funds
+fn charge_back_withdrawal(
+    transaction_state: &mut TransactionRunningState,
+    tx: u32,
+    credited: f64,
+    amount: f64,
+) {
+    if credited > 0.0 {
+        transaction_state.chargeback_provisional_credit(tx, credited);
+    }
+    if amount > credited {
+        transaction_state.chargeback_withdrawal(tx, amount - credited);
+    }
+}
+
+/// Flag an account whose available funds have just gone negative
+fn negative_balance_alert(
+    transaction: &TransactionInput,
+    transaction_state: &TransactionRunningState,
+) -> Alert {
+    let available = transaction_state
+        .precision()
+        .round(transaction_state.available());
+    Alert {
+        client: transaction.client(),
+        tx: transaction.tx(),
+        rule: "negative_balance",
+        detail: format!(
+            "available {} after {}",
+            available,
+            transaction.transaction_type().name()
+        ),
+        locked: false,
+    }
+}
+
+fn check_duplicate(disputes: &Disputes, policy: &Policy, tx: u32) -> Result<(), Rejection> {
+    if policy.input.duplicates == Duplicates::Reject && disputes.find(tx).is_ok() {
+        Err(Rejection::DuplicateTransaction)
+    } else {
+        Ok(())
     }
 }
 
+/// Zero amounts the policy applies without touching the balances
+fn is_ignored_zero(policy: &Policy, amount: f64) -> bool {
+    amount == 0.0 && policy.amounts.zero == ZeroAmounts::Ignore
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transaction_engine::{parse_timestamp, Account};
 
     #[test]
     fn handle_deposit_transaction() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(1.1111_f64),
-        };
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(1.1111_f64));
 
-        test_obj.add_transaction(deposit_transaction);
+        let _ = test_obj.apply(deposit_transaction);
 
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 1.1111_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 1.1111_f64);
-        assert_eq!(process_transactions.locked, false);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 1.1111_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 1.1111_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_deposit_and_withdrawal() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(1.1111_f64),
-        };
-        let withdrawal_transaction = TransactionInput {
-            transaction_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 2,
-            amount: Some(0.1111_f64),
-        };
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(1.1111_f64));
+        let withdrawal_transaction =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(0.1111_f64));
 
-        test_obj.add_transaction(deposit_transaction);
-        test_obj.add_transaction(withdrawal_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 1.000_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 1.000_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction);
+        let _ = test_obj.apply(withdrawal_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 1.000_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 1.000_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_ignore_withdraw_more_than_available_funds() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(1.0_f64),
-        };
-        let withdrawal_transaction = TransactionInput {
-            transaction_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 2,
-            amount: Some(2.0_f64),
-        };
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(1.0_f64));
+        let withdrawal_transaction =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(2.0_f64));
 
-        test_obj.add_transaction(deposit_transaction);
-        test_obj.add_transaction(withdrawal_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 1.000_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 1.000_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction);
+        let _ = test_obj.apply(withdrawal_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 1.000_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 1.000_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_dispute_amount() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 150.0_f64);
-        assert_eq!(process_transactions.held, 50.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+        assert_eq!(process_transactions.held(), 50.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_resolved_amount() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
-        let resolved_transaction = TransactionInput {
-            transaction_type: TransactionType::Resolve,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let resolved_transaction = TransactionInput::new(TransactionType::Resolve, 1, 2, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        test_obj.add_transaction(resolved_transaction);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(resolved_transaction);
 
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 150.0_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 150.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 150.0_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_chargeback_deposit_amount() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
-        let chargeback_transaction = TransactionInput {
-            transaction_type: TransactionType::Chargeback,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let chargeback_transaction = TransactionInput::new(TransactionType::Chargeback, 1, 2, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        test_obj.add_transaction(chargeback_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 100.0_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, true);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(chargeback_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(process_transactions.locked());
     }
     #[test]
     fn handle_chargeback_withdrawal_amount() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
-        let chargeback_transaction = TransactionInput {
-            transaction_type: TransactionType::Chargeback,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let chargeback_transaction = TransactionInput::new(TransactionType::Chargeback, 1, 2, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        test_obj.add_transaction(chargeback_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 100.0_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, true);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(chargeback_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(process_transactions.locked());
     }
 
     #[test]
     fn handle_ignore_chargeback_if_invalid_transaction() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 2,
-            amount: None,
-        };
-        let chargeback_transaction = TransactionInput {
-            transaction_type: TransactionType::Chargeback,
-            client: 1,
-            tx: 99,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let chargeback_transaction =
+            TransactionInput::new(TransactionType::Chargeback, 1, 99, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        test_obj.add_transaction(chargeback_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 150.0_f64);
-        assert_eq!(process_transactions.held, 50.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(chargeback_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+        assert_eq!(process_transactions.held(), 50.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(!process_transactions.locked());
     }
     #[test]
     fn handle_ignore_dispute_if_invalid_transaction() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
-        };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
-            client: 1,
-            tx: 99,
-            amount: None,
-        };
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 99, None);
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 150.0_f64);
-        assert_eq!(process_transactions.available, 150.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+        assert_eq!(process_transactions.available(), 150.0_f64);
+        assert!(!process_transactions.locked());
     }
 
     #[test]
     fn handle_ignore_resolve_if_invalid_transaction() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(100.0),
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let resolved_transaction = TransactionInput::new(TransactionType::Dispute, 1, 99, None);
+
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(resolved_transaction);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+        assert_eq!(process_transactions.held(), 50.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(!process_transactions.locked());
+    }
+
+    #[test]
+    fn handle_ignore_deposit_if_zero_amount() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(0.0));
+
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert!(!process_transactions.locked());
+    }
+    #[test]
+
+    fn handle_ignore_deposit_if_infinity_amount() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let deposit_transaction_1 =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(f64::MAX));
+        let deposit_transaction_2 =
+            TransactionInput::new(TransactionType::Deposit, 1, 2, Some(1.0));
+
+        let _ = test_obj.apply(deposit_transaction_1);
+        let _ = test_obj.apply(deposit_transaction_2);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), f64::MAX);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.available(), f64::MAX);
+        assert!(!process_transactions.locked());
+    }
+
+    #[test]
+    fn handle_reject_withdrawal_over_limit() {
+        let limits = ClientLimits {
+            max_withdrawal: Some(10.0),
+            ..ClientLimits::default()
         };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(50.0),
+        let mut test_obj = TransactionProcessor::new(1).with_limits(limits);
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let withdrawal_transaction_1 =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(50.0));
+        let withdrawal_transaction_2 =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 3, Some(10.0));
+
+        let _ = test_obj.apply(deposit_transaction);
+        let _ = test_obj.apply(withdrawal_transaction_1);
+        let _ = test_obj.apply(withdrawal_transaction_2);
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 90.0_f64);
+        assert_eq!(process_transactions.available(), 90.0_f64);
+    }
+
+    #[test]
+    fn handle_reject_deposit_over_window_volume() {
+        let limits = ClientLimits {
+            window_size: Some(2),
+            max_window_volume: Some(100.0),
+            ..ClientLimits::default()
         };
-        let disputed_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
+        let mut test_obj = TransactionProcessor::new(1).with_limits(limits);
+
+        for (tx, amount) in [(1, 60.0), (2, 50.0), (3, 40.0), (4, 50.0)].iter() {
+            let _ = test_obj.apply(TransactionInput::new(
+                TransactionType::Deposit,
+                1,
+                *tx,
+                Some(*amount),
+            ));
+        }
+
+        // tx 2 breaches the window (60 + 50), tx 4 fits once tx 1 has left it (40 + 50)
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 150.0_f64);
+    }
+
+    #[test]
+    fn handle_reject_dispute_over_open_dispute_limit() {
+        let limits = ClientLimits {
+            max_open_disputes: Some(1),
+            ..ClientLimits::default()
+        };
+        let mut test_obj = TransactionProcessor::new(1).with_limits(limits);
+
+        for tx in 1..=2 {
+            let _ = test_obj.apply(TransactionInput::new(
+                TransactionType::Deposit,
+                1,
+                tx,
+                Some(50.0),
+            ));
+        }
+        for tx in 1..=2 {
+            let _ = test_obj.apply(TransactionInput::new(TransactionType::Dispute, 1, tx, None));
+        }
+
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 50.0_f64);
+        assert_eq!(process_transactions.available(), 50.0_f64);
+    }
+
+    #[test]
+    fn handle_alert_and_lock_on_rule() {
+        let rules = Rules::from_toml(
+            r#"
+            [deposit_withdrawal]
+            auto_lock = true
+            "#,
+        )
+        .unwrap();
+        let mut test_obj = TransactionProcessor::new(1).with_rules(Rc::new(rules));
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let withdrawal_transaction =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(100.0));
+
+        let _ = test_obj.apply(deposit_transaction);
+        let alerts = test_obj.apply(withdrawal_transaction).unwrap().alerts;
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 0.0_f64);
+        assert!(process_transactions.locked());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "deposit_withdrawal");
+        assert_eq!(alerts[0].tx, 2);
+    }
+
+    #[test]
+    fn handle_rules_over_applied_transactions_only() {
+        let rules = Rules::from_toml(
+            r#"
+            [deposit_withdrawal]
+
+            [dispute_ratio]
+            max_ratio = 0.5
+            "#,
+        )
+        .unwrap();
+        let mut test_obj = TransactionProcessor::new(1).with_rules(Rc::new(rules));
+
+        test_obj
+            .apply(TransactionInput::deposit(1, 1, 100.0))
+            .unwrap();
+        test_obj
+            .apply(TransactionInput::deposit(1, 2, 50.0))
+            .unwrap();
+        // Rejected rows are not part of the history the rules see
+        test_obj
+            .apply(TransactionInput::deposit(1, 3, -5.0))
+            .unwrap_err();
+        test_obj.apply(TransactionInput::dispute(1, 9)).unwrap_err();
+        let withdrawal = test_obj.apply(TransactionInput::withdrawal(1, 4, 5.0));
+        let dispute = test_obj.apply(TransactionInput::dispute(1, 1));
+
+        assert!(withdrawal.unwrap().alerts.is_empty());
+        assert!(dispute.unwrap().alerts.is_empty());
+        assert_eq!(test_obj.history.count(TransactionType::Dispute), 1);
+        assert_eq!(test_obj.history.recent().len(), 1);
+    }
+
+    #[test]
+    fn handle_admin_unlock_and_adjust() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 1, None);
+        let chargeback_transaction = TransactionInput::new(TransactionType::Chargeback, 1, 1, None);
+        let unlock_transaction = AdminInput {
+            action: AdminAction::Unlock,
             client: 1,
             tx: 2,
             amount: None,
+            reason: None,
+            timestamp: None,
         };
-        let resolved_transaction = TransactionInput {
-            transaction_type: TransactionType::Dispute,
+        let adjust_transaction = AdminInput {
+            action: AdminAction::Adjust,
             client: 1,
-            tx: 99,
-            amount: None,
+            tx: 3,
+            amount: Some(25.0),
+            reason: Some(String::from("goodwill")),
+            timestamp: None,
         };
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        test_obj.add_transaction(disputed_transaction);
-        test_obj.add_transaction(resolved_transaction);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 150.0_f64);
-        assert_eq!(process_transactions.held, 50.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        let _ = test_obj.apply(deposit_transaction);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(chargeback_transaction);
+        assert!(test_obj.running_state().locked());
+        let _ = test_obj.apply_admin(&unlock_transaction);
+        let adjust_entry = test_obj.apply_admin(&adjust_transaction).unwrap().journal;
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 25.0_f64);
+        assert_eq!(process_transactions.available(), 25.0_f64);
+        assert!(!process_transactions.locked());
+        assert_eq!(
+            adjust_entry.journal_type,
+            JournalType::Admin(AdminAction::Adjust)
+        );
+        assert_eq!(adjust_entry.reason.as_deref(), Some("goodwill"));
     }
 
     #[test]
-    fn handle_ignore_deposit_if_zero_amount() {
+    fn handle_reject_adjust_without_reason() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
+        let adjust_transaction = AdminInput {
+            action: AdminAction::Adjust,
             client: 1,
             tx: 1,
-            amount: Some(100.0),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(0.0),
+            amount: Some(25.0),
+            reason: None,
+            timestamp: None,
         };
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, 100.0_f64);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, 100.0_f64);
-        assert_eq!(process_transactions.locked, false);
+        assert_eq!(
+            test_obj.apply_admin(&adjust_transaction),
+            Err(Rejection::MissingReason)
+        );
+        assert_eq!(test_obj.running_state().total(), 0.0_f64);
     }
+
     #[test]
+    fn handle_ledger_balances_after_chargeback_withdrawal() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let deposit_transaction =
+            TransactionInput::new(TransactionType::Deposit, 1, 1, Some(100.0));
+        let withdrawal_transaction =
+            TransactionInput::new(TransactionType::Withdrawal, 1, 2, Some(50.0));
+        let disputed_transaction = TransactionInput::new(TransactionType::Dispute, 1, 2, None);
+        let chargeback_transaction = TransactionInput::new(TransactionType::Chargeback, 1, 2, None);
 
-    fn handle_ignore_deposit_if_infinity_amount() {
+        let _ = test_obj.apply(deposit_transaction);
+        let _ = test_obj.apply(withdrawal_transaction);
+        let _ = test_obj.apply(disputed_transaction);
+        let _ = test_obj.apply(chargeback_transaction);
+        let process_transactions = test_obj.running_state();
+        let ledger = process_transactions.ledger();
+        assert!(ledger.trial_balance().is_balanced());
+        assert_eq!(ledger.balance(Account::HouseCash), 50.0_f64);
+        assert_eq!(ledger.balance(Account::ChargebackLoss), 50.0_f64);
+        assert_eq!(
+            ledger.balance(Account::ClientAvailable) + ledger.balance(Account::ClientHeld),
+            process_transactions.total()
+        );
+    }
+
+    #[test]
+    fn handle_ignore_non_finite_amounts() {
         let mut test_obj = TransactionProcessor::new(1);
-        let deposit_transaction_1 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(f64::MAX),
-        };
-        let deposit_transaction_2 = TransactionInput {
-            transaction_type: TransactionType::Deposit,
-            client: 1,
-            tx: 2,
-            amount: Some(1.0),
-        };
+        let mut rejections = Vec::new();
+        for (tx, transaction_type, amount) in [
+            (1, TransactionType::Deposit, f64::INFINITY),
+            (2, TransactionType::Deposit, f64::NAN),
+            (3, TransactionType::Deposit, 10.0),
+            (4, TransactionType::Withdrawal, f64::NAN),
+            (5, TransactionType::Withdrawal, -10.0),
+        ]
+        .iter()
+        {
+            let transaction = TransactionInput::new(*transaction_type, 1, *tx, Some(*amount));
+            rejections.push(test_obj.apply(transaction).err());
+        }
+
+        assert_eq!(
+            rejections,
+            vec![
+                Some(Rejection::InvalidAmount),
+                Some(Rejection::InvalidAmount),
+                None,
+                Some(Rejection::InvalidAmount),
+                Some(Rejection::InvalidAmount),
+            ]
+        );
+        assert_eq!(test_obj.running_state().total(), 10.0_f64);
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingListener {
+        events: Rc<std::cell::RefCell<Vec<(EventKind, u32)>>>,
+    }
+
+    impl crate::transaction_engine::Listener for RecordingListener {
+        fn on_event(&self, event: &Event) {
+            self.events
+                .borrow_mut()
+                .push((event.kind, event.transaction.tx()));
+        }
+    }
+
+    #[test]
+    fn handle_notify_listeners() {
+        let listener = RecordingListener::default();
+        let events = Rc::clone(&listener.events);
+        let listeners = Listeners::new().with_listener(listener);
+        let mut test_obj = TransactionProcessor::new(1).with_listeners(Rc::new(listeners));
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 500.0));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                (EventKind::Applied, 1),
+                (EventKind::Rejected, 2),
+                (EventKind::Applied, 1),
+                (EventKind::DisputeOpened, 1),
+                (EventKind::Applied, 1),
+                (EventKind::Chargeback, 1),
+                (EventKind::AccountLocked, 1),
+            ]
+        );
+    }
+
+    fn processor_with_policy(policy: &str) -> TransactionProcessor {
+        TransactionProcessor::new(1).with_policy(Rc::new(Policy::from_toml(policy).unwrap()))
+    }
+
+    #[test]
+    fn handle_policy_zero_amounts_and_overdraft() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [amounts]
+            zero = "ignore"
+            allow_overdraft = true
+            "#,
+        );
+
+        assert_eq!(
+            test_obj
+                .apply(TransactionInput::deposit(1, 1, 0.0))
+                .map(|o| o.amount),
+            Ok(0.0)
+        );
+        let _ = test_obj.apply(TransactionInput::deposit(1, 2, 10.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 3, 15.0));
+
+        assert_eq!(test_obj.running_state().available(), -5.0_f64);
+        assert!(test_obj
+            .running_state()
+            .ledger()
+            .trial_balance()
+            .is_balanced());
+    }
+
+    #[test]
+    fn handle_policy_locking() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [locking]
+            lock_on_chargeback = false
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+        assert!(!test_obj.running_state().locked());
+
+        let mut test_obj = processor_with_policy(
+            r#"
+            [locking]
+            reject_when_locked = false
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+        assert!(test_obj.apply(TransactionInput::deposit(1, 2, 5.0)).is_ok());
+        assert!(test_obj.running_state().locked());
+        assert_eq!(test_obj.running_state().total(), 5.0_f64);
+    }
+
+    #[test]
+    fn handle_policy_withdrawal_disputes_and_duplicates() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            withdrawals = "reject"
+
+            [input]
+            duplicates = "reject"
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 4.0));
+
+        assert_eq!(
+            test_obj.apply(TransactionInput::dispute(1, 2)).err(),
+            Some(Rejection::NotDisputable)
+        );
+        assert_eq!(
+            test_obj.apply(TransactionInput::deposit(1, 1, 10.0)).err(),
+            Some(Rejection::DuplicateTransaction)
+        );
+        assert_eq!(test_obj.running_state().available(), 6.0_f64);
+        assert_eq!(test_obj.running_state().held(), 0.0_f64);
+    }
+
+    #[test]
+    fn handle_policy_precision() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [amounts]
+            precision = 2
+            rounding = "truncate"
+            "#,
+        );
+
+        let journal = test_obj
+            .apply(TransactionInput::deposit(1, 1, 1.23456))
+            .unwrap()
+            .journal;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(Vec::new());
+        writer.serialize(test_obj.running_state()).unwrap();
+        writer.serialize(journal).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(test_obj.running_state().available(), 1.23456_f64);
+        assert_eq!(
+            output,
+            "1,1.23,0.0,1.23,false\n1,1,client,deposit,1.23456,,,1.23,0.0,1.23,false\n"
+        );
+    }
+
+    #[test]
+    fn handle_withdrawal_dispute_provisional_credit() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 50.0));
+
+        let _ = test_obj.apply(TransactionInput::dispute(1, 2));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 50.0_f64);
+        assert_eq!(process_transactions.held(), 50.0_f64);
+        assert_eq!(process_transactions.total(), 100.0_f64);
+
+        let _ = test_obj.apply(TransactionInput::resolve(1, 2));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 50.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.total(), 50.0_f64);
+
+        let _ = test_obj.apply(TransactionInput::dispute(1, 2));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 2));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(process_transactions.total(), 100.0_f64);
+        assert!(process_transactions.locked());
+
+        let ledger = process_transactions.ledger();
+        assert!(ledger.trial_balance().is_balanced());
+        assert_eq!(ledger.balance(Account::DisputeCredit), 0.0_f64);
+        assert_eq!(ledger.balance(Account::ChargebackLoss), 50.0_f64);
+    }
+
+    #[test]
+    fn handle_withdrawal_dispute_no_hold() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            withdrawals = "no_hold"
+            "#,
+        );
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 50.0));
+
+        let _ = test_obj.apply(TransactionInput::dispute(1, 2));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 50.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+
+        assert!(test_obj.apply(TransactionInput::resolve(1, 2)).is_ok());
+        assert_eq!(test_obj.running_state().total(), 50.0_f64);
+
+        let _ = test_obj.apply(TransactionInput::dispute(1, 2));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 2));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 100.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert!(process_transactions.locked());
+
+        let ledger = process_transactions.ledger();
+        assert!(ledger.trial_balance().is_balanced());
+        assert_eq!(ledger.balance(Account::DisputeCredit), 0.0_f64);
+        assert_eq!(ledger.balance(Account::ChargebackLoss), 50.0_f64);
+    }
+
+    #[test]
+    fn handle_dispute_spent_deposit_allow() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 80.0));
+
+        let outcome = test_obj.apply(TransactionInput::dispute(1, 1)).unwrap();
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), -80.0_f64);
+        assert_eq!(process_transactions.held(), 100.0_f64);
+        assert_eq!(outcome.alerts.len(), 1);
+        assert_eq!(outcome.alerts[0].rule, "negative_balance");
+    }
+
+    #[test]
+    fn handle_dispute_spent_deposit_cap() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            spent_deposits = "cap"
+            "#,
+        );
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 80.0));
+
+        let outcome = test_obj.apply(TransactionInput::dispute(1, 1)).unwrap();
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 0.0_f64);
+        assert_eq!(process_transactions.held(), 20.0_f64);
+        assert!(outcome.alerts.is_empty());
+
+        let _ = test_obj.apply(TransactionInput::resolve(1, 1));
+        assert_eq!(test_obj.running_state().available(), 20.0_f64);
+
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 0.0_f64);
+        assert_eq!(process_transactions.total(), 0.0_f64);
+
+        let ledger = process_transactions.ledger();
+        assert!(ledger.trial_balance().is_balanced());
+        assert_eq!(ledger.balance(Account::ChargebackLoss), 80.0_f64);
+    }
+
+    #[test]
+    fn handle_dispute_spent_deposit_reject() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            spent_deposits = "reject"
+            "#,
+        );
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 80.0));
+
+        assert_eq!(
+            test_obj.apply(TransactionInput::dispute(1, 1)).err(),
+            Some(Rejection::InsufficientFunds)
+        );
+        assert_eq!(test_obj.running_state().available(), 20.0_f64);
+        assert_eq!(test_obj.running_state().held(), 0.0_f64);
+    }
+
+    #[test]
+    fn handle_partial_disputes() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let partial =
+            |transaction_type, amount| TransactionInput::new(transaction_type, 1, 1, Some(amount));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 100.0));
+
+        let _ = test_obj.apply(partial(TransactionType::Dispute, 30.0));
+        let _ = test_obj.apply(partial(TransactionType::Dispute, 50.0));
+        assert_eq!(test_obj.running_state().held(), 80.0_f64);
+        assert_eq!(
+            test_obj
+                .apply(partial(TransactionType::Dispute, 30.0))
+                .err(),
+            Some(Rejection::AmountTooLarge)
+        );
+
+        let _ = test_obj.apply(partial(TransactionType::Resolve, 20.0));
+        let _ = test_obj.apply(partial(TransactionType::Chargeback, 40.0));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.available(), 40.0_f64);
+        assert_eq!(process_transactions.held(), 20.0_f64);
+        assert_eq!(process_transactions.total(), 60.0_f64);
+        assert!(process_transactions.locked());
+        assert_eq!(
+            test_obj
+                .apply(partial(TransactionType::Chargeback, 30.0))
+                .err(),
+            Some(Rejection::AmountTooLarge)
+        );
+
+        // The rest of the dispute, then the rest of the transaction
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::chargeback(1, 1));
+        let process_transactions = test_obj.running_state();
+        assert_eq!(process_transactions.total(), 0.0_f64);
+        assert_eq!(process_transactions.held(), 0.0_f64);
+        assert_eq!(
+            test_obj.apply(TransactionInput::dispute(1, 1)).err(),
+            Some(Rejection::AlreadyChargedBack)
+        );
+    }
+
+    #[test]
+    fn handle_partial_disputes_within_rounding_error() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 0.3));
+
+        for amount in [0.1, 0.1, 0.1].iter() {
+            let dispute = TransactionInput::new(TransactionType::Dispute, 1, 1, Some(*amount));
+            assert!(test_obj.apply(dispute).is_ok());
+        }
+
+        assert_eq!(
+            test_obj.apply(TransactionInput::dispute(1, 1)).err(),
+            Some(Rejection::AlreadyDisputed)
+        );
+        let _ = test_obj.apply(TransactionInput::resolve(1, 1));
+        assert_eq!(test_obj.running_state().held(), 0.0_f64);
+    }
+
+    fn at(day: u32) -> Timestamp {
+        parse_timestamp(&format!("2024-01-{:02}T00:00:00Z", day)).unwrap()
+    }
+
+    #[test]
+    fn handle_policy_ordering() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [input]
+            ordering = "validate"
+            "#,
+        );
+
+        assert!(test_obj
+            .apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(at(2)))
+            .is_ok());
+        assert_eq!(
+            test_obj.apply(TransactionInput::deposit(1, 2, 10.0)).err(),
+            Some(Rejection::MissingTimestamp)
+        );
+        assert_eq!(
+            test_obj
+                .apply(TransactionInput::deposit(1, 3, 10.0).with_timestamp(at(1)))
+                .err(),
+            Some(Rejection::OutOfOrder)
+        );
+        assert!(test_obj
+            .apply(TransactionInput::withdrawal(1, 4, 4.0).with_timestamp(at(2)))
+            .is_ok());
+        assert_eq!(test_obj.running_state().available(), 6.0_f64);
+    }
+
+    #[test]
+    fn handle_policy_dispute_window() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            window_days = 10
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(at(1)));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 2, 10.0).with_timestamp(at(1)));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 3, 10.0));
+
+        assert!(test_obj
+            .apply(TransactionInput::dispute(1, 1).with_timestamp(at(11)))
+            .is_ok());
+        assert_eq!(
+            test_obj
+                .apply(TransactionInput::dispute(1, 2).with_timestamp(at(12)))
+                .err(),
+            Some(Rejection::DisputeWindowExpired)
+        );
+        // Without timestamps on both rows the window can not be checked
+        assert!(test_obj
+            .apply(TransactionInput::dispute(1, 3).with_timestamp(at(30)))
+            .is_ok());
+        assert_eq!(test_obj.running_state().held(), 20.0_f64);
+    }
+
+    #[test]
+    fn handle_balance_as_of() {
+        let mut test_obj = TransactionProcessor::new(1);
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(at(2)));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 2, 4.0).with_timestamp(at(4)));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 3, 40.0).with_timestamp(at(5)));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1).with_timestamp(at(6)));
+
+        assert_eq!(test_obj.balance_as_of(at(1)), None);
+        assert_eq!(test_obj.balance_as_of(at(3)).unwrap().available, 10.0_f64);
+        // The rejected withdrawal leaves the balances as they were
+        let balance = test_obj.balance_as_of(at(5)).unwrap();
+        assert_eq!((balance.available, balance.held), (6.0_f64, 0.0_f64));
+        let balance = test_obj.balance_as_of(at(6)).unwrap();
+        assert_eq!((balance.available, balance.held), (-4.0_f64, 10.0_f64));
+    }
+
+    #[test]
+    fn handle_dispute_expiry_by_transactions() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [disputes]
+            expire_after_transactions = 2
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 2, 5.0));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 3, 5.0));
+        assert!(test_obj.expired().is_empty());
+        assert_eq!(test_obj.open_disputes(None)[0].rows_since, 2);
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 4, 5.0));
+        let expired = test_obj.expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(
+            expired[0].journal_type,
+            JournalType::Expiry(TransactionType::Resolve)
+        );
+        assert_eq!(expired[0].amount, Some(10.0_f64));
+        assert_eq!(expired[0].held, 0.0_f64);
+        assert!(test_obj.open_disputes(None).is_empty());
+        assert_eq!(
+            test_obj.apply(TransactionInput::resolve(1, 1)).err(),
+            Some(Rejection::NotDisputed)
+        );
+        assert_eq!(test_obj.running_state().available(), 25.0_f64);
+    }
+
+    #[test]
+    fn handle_dispute_expiry_after_the_order_check() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [input]
+            ordering = "validate"
+
+            [disputes]
+            expire_after_transactions = 1
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(at(1)));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1).with_timestamp(at(2)));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 2, 5.0).with_timestamp(at(2)));
+        assert_eq!(
+            test_obj
+                .apply(TransactionInput::deposit(1, 3, 5.0).with_timestamp(at(1)))
+                .err(),
+            Some(Rejection::OutOfOrder)
+        );
+        assert!(test_obj.expired().is_empty());
+        assert_eq!(test_obj.running_state().held(), 10.0_f64);
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 4, 5.0).with_timestamp(at(3)));
+        assert_eq!(test_obj.expired().len(), 1);
+        assert_eq!(test_obj.running_state().held(), 0.0_f64);
+    }
+
+    #[test]
+    fn handle_dispute_expiry_by_days() {
+        let mut test_obj = processor_with_policy(
+            r#"
+            [locking]
+            reject_when_locked = true
+
+            [disputes]
+            expire_after_days = 30
+            on_expiry = "chargeback"
+            "#,
+        );
+
+        let _ = test_obj.apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(at(1)));
+        let _ = test_obj.apply(TransactionInput::deposit(1, 2, 5.0).with_timestamp(at(1)));
+        let _ = test_obj.apply(TransactionInput::dispute(1, 1).with_timestamp(at(2)));
+        let _ = test_obj.apply(TransactionInput::withdrawal(1, 3, 1.0).with_timestamp(at(31)));
+        assert!(test_obj.expired().is_empty());
 
-        test_obj.add_transaction(deposit_transaction_1);
-        test_obj.add_transaction(deposit_transaction_2);
-        let process_transactions = test_obj.process_transactions();
-        assert_eq!(process_transactions.total, f64::MAX);
-        assert_eq!(process_transactions.held, 0.0_f64);
-        assert_eq!(process_transactions.available, f64::MAX);
-        assert_eq!(process_transactions.locked, false);
+        // The chargeback locks the account before the deposit is applied
+        let rejection = test_obj
+            .apply(TransactionInput::deposit(1, 4, 1.0).with_timestamp(at(3) + Duration::days(30)))
+            .err();
+        assert_eq!(
+            test_obj.expired()[0].journal_type,
+            JournalType::Expiry(TransactionType::Chargeback)
+        );
+        assert_eq!(rejection, Some(Rejection::AccountLocked));
+        let state = test_obj.running_state();
+        assert_eq!((state.available(), state.held()), (4.0_f64, 0.0_f64));
+        assert!(state.locked());
     }
 }
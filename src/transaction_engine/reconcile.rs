@@ -0,0 +1,153 @@
+use super::{Balance, TransactionRunningState};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+    MissingClient,
+    ExtraClient,
+    Mismatch,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Difference {
+    pub client: u16,
+    pub kind: DifferenceKind,
+    pub field: Option<&'static str>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Difference {
+    fn client(client: u16, kind: DifferenceKind) -> Self {
+        Difference {
+            client,
+            kind,
+            field: None,
+            expected: None,
+            actual: None,
+        }
+    }
+
+    fn mismatch<T: ToString>(client: u16, field: &'static str, expected: T, actual: T) -> Self {
+        Difference {
+            client,
+            kind: DifferenceKind::Mismatch,
+            field: Some(field),
+            expected: Some(expected.to_string()),
+            actual: Some(actual.to_string()),
+        }
+    }
+}
+
+/// Compare computed balances against the expected balances read back in the output layout, amounts are compared at the
+/// output precision and may differ by up to `tolerance`.
+pub fn reconcile(
+    actual: &[TransactionRunningState],
+    expected: &[Balance],
+    tolerance: f64,
+) -> Vec<Difference> {
+    let actual: BTreeMap<u16, &TransactionRunningState> =
+        actual.iter().map(|state| (state.client(), state)).collect();
+    let expected: BTreeMap<u16, &Balance> =
+        expected.iter().map(|state| (state.client, state)).collect();
+    let mut differences = Vec::new();
+
+    for (client, expected_state) in &expected {
+        let actual_state = match actual.get(client) {
+            Some(actual_state) => actual_state,
+            None => {
+                differences.push(Difference::client(*client, DifferenceKind::MissingClient));
+                continue;
+            }
+        };
+
+        let amounts = [
+            (
+                "available",
+                expected_state.available,
+                actual_state.available(),
+            ),
+            ("held", expected_state.held, actual_state.held()),
+            ("total", expected_state.total, actual_state.total()),
+        ];
+        for (field, expected_amount, actual_amount) in amounts.iter() {
+            let actual_amount = actual_state.precision().round(*actual_amount);
+            // NaN never falls within the tolerance
+            let within_tolerance = (expected_amount - actual_amount).abs() <= tolerance;
+            if !within_tolerance {
+                differences.push(Difference::mismatch(
+                    *client,
+                    field,
+                    *expected_amount,
+                    actual_amount,
+                ));
+            }
+        }
+        if expected_state.locked != actual_state.locked() {
+            differences.push(Difference::mismatch(
+                *client,
+                "locked",
+                expected_state.locked,
+                actual_state.locked(),
+            ));
+        }
+    }
+
+    for client in actual
+        .keys()
+        .filter(|client| !expected.contains_key(client))
+    {
+        differences.push(Difference::client(*client, DifferenceKind::ExtraClient));
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected_balance(client: u16, amount: f64) -> Balance {
+        Balance {
+            client,
+            available: amount,
+            held: 0.0,
+            total: amount,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn report_missing_extra_and_mismatched_clients() {
+        let mut client_1 = TransactionRunningState::new(1);
+        client_1.deposit(1, 10.0).unwrap();
+        let mut client_2 = TransactionRunningState::new(2);
+        client_2.deposit(2, 20.0).unwrap();
+
+        let expected = vec![expected_balance(1, 10.00001), expected_balance(3, 5.0)];
+        let differences = reconcile(&[client_1, client_2], &expected, 0.0001);
+
+        assert_eq!(
+            differences,
+            vec![
+                Difference::client(3, DifferenceKind::MissingClient),
+                Difference::client(2, DifferenceKind::ExtraClient),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_field_mismatch_outside_tolerance() {
+        let mut client_1 = TransactionRunningState::new(1);
+        client_1.deposit(1, 10.0).unwrap();
+        client_1.lock();
+
+        let expected = vec![expected_balance(1, 10.5)];
+        let differences = reconcile(&[client_1], &expected, 0.0);
+
+        let fields: Vec<&str> = differences.iter().filter_map(|d| d.field).collect();
+        assert_eq!(fields, vec!["available", "total", "locked"]);
+    }
+}
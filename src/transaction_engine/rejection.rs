@@ -0,0 +1,57 @@
+use super::Limit;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    InvalidAmount,
+    InsufficientFunds,
+    TransactionNotFound,
+    MissingReason,
+    AccountLocked,
+    AlreadyDisputed,
+    AlreadyChargedBack,
+    NotDisputed,
+    NotDisputable,
+    DuplicateTransaction,
+    /// A partial dispute, resolve or chargeback for more than is left to act on
+    AmountTooLarge,
+    /// The row has no timestamp and the policy orders rows by timestamp
+    MissingTimestamp,
+    /// The row's timestamp is earlier than the client's previous row
+    OutOfOrder,
+    /// The dispute was raised after the policy's dispute window closed
+    DisputeWindowExpired,
+    LimitExceeded(Limit),
+}
+
+impl Rejection {
+    /// Stable reason code used when reporting a rejected transaction
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::InvalidAmount => "invalid_amount",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::TransactionNotFound => "transaction_not_found",
+            Rejection::MissingReason => "missing_reason",
+            Rejection::AccountLocked => "account_locked",
+            Rejection::AlreadyDisputed => "already_disputed",
+            Rejection::AlreadyChargedBack => "already_charged_back",
+            Rejection::NotDisputed => "not_disputed",
+            Rejection::NotDisputable => "not_disputable",
+            Rejection::DuplicateTransaction => "duplicate_transaction",
+            Rejection::AmountTooLarge => "amount_too_large",
+            Rejection::MissingTimestamp => "missing_timestamp",
+            Rejection::OutOfOrder => "out_of_order",
+            Rejection::DisputeWindowExpired => "dispute_window_expired",
+            Rejection::LimitExceeded(_) => "limit_exceeded",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::LimitExceeded(limit) => write!(f, "{} ({})", self.code(), limit.name()),
+            _ => write!(f, "{}", self.code()),
+        }
+    }
+}
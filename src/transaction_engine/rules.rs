@@ -0,0 +1,370 @@
+use super::{TransactionInput, TransactionRunningState, TransactionType};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub client: u16,
+    pub tx: u32,
+    pub rule: &'static str,
+    pub detail: String,
+    pub locked: bool,
+}
+
+/// A fraud signal evaluated after each transaction is applied to a client.
+///
+/// `history` holds the client's applied transactions preceding `transaction`.
+pub trait Rule: fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    /// Number of the most recent applied transactions the rule looks back over
+    fn lookback(&self) -> usize {
+        0
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &TransactionInput,
+        state: &TransactionRunningState,
+        history: &History,
+    ) -> Option<String>;
+}
+
+/// The client's applied transactions the rules look back over, a running count of each type
+/// along with the most recent transactions, only as many as the rules need.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    counts: [usize; 5],
+    /// Oldest first
+    recent: VecDeque<TransactionInput>,
+    lookback: usize,
+}
+
+impl History {
+    pub fn new(lookback: usize) -> Self {
+        History {
+            counts: [0; 5],
+            recent: VecDeque::new(),
+            lookback,
+        }
+    }
+
+    pub fn record(&mut self, transaction: &TransactionInput) {
+        self.counts[transaction.transaction_type() as usize] += 1;
+        if self.lookback > 0 {
+            self.recent.push_back(transaction.clone());
+            while self.recent.len() > self.lookback {
+                self.recent.pop_front();
+            }
+        }
+    }
+
+    pub fn count(&self, transaction_type: TransactionType) -> usize {
+        self.counts[transaction_type as usize]
+    }
+
+    /// Count of each type in declaration order
+    pub fn counts(&self) -> &[usize; 5] {
+        &self.counts
+    }
+
+    /// The most recent applied transactions, oldest first
+    pub fn recent(&self) -> &VecDeque<TransactionInput> {
+        &self.recent
+    }
+
+    /// Carry the counts and recent transactions over from a snapshot
+    pub(crate) fn restore(&mut self, counts: [usize; 5], recent: Vec<TransactionInput>) {
+        self.counts = counts;
+        self.recent.clear();
+        recent.iter().for_each(|transaction| {
+            self.recent.push_back(transaction.clone());
+            while self.recent.len() > self.lookback {
+                self.recent.pop_front();
+            }
+        });
+    }
+}
+
+/// Raise an alert once the share of disputed transactions goes above `max_ratio`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeRatio {
+    pub max_ratio: f64,
+    /// Deposits/withdrawals required before the ratio is considered meaningful
+    #[serde(default)]
+    pub min_transactions: usize,
+}
+
+impl Rule for DisputeRatio {
+    fn name(&self) -> &'static str {
+        "dispute_ratio"
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &TransactionInput,
+        _state: &TransactionRunningState,
+        history: &History,
+    ) -> Option<String> {
+        if transaction.transaction_type() != TransactionType::Dispute {
+            return None;
+        }
+
+        let disputes = history.count(TransactionType::Dispute) + 1;
+        let transactions =
+            history.count(TransactionType::Deposit) + history.count(TransactionType::Withdrawal);
+        if transactions == 0 || transactions < self.min_transactions {
+            return None;
+        }
+
+        let ratio = disputes as f64 / transactions as f64;
+        if ratio > self.max_ratio {
+            Some(format!(
+                "{} disputes over {} transactions",
+                disputes, transactions
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Raise an alert when a deposit is immediately followed by a withdrawal of the full amount.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepositWithdrawal {}
+
+impl Rule for DepositWithdrawal {
+    fn name(&self) -> &'static str {
+        "deposit_withdrawal"
+    }
+
+    fn lookback(&self) -> usize {
+        1
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &TransactionInput,
+        _state: &TransactionRunningState,
+        history: &History,
+    ) -> Option<String> {
+        if transaction.transaction_type() != TransactionType::Withdrawal {
+            return None;
+        }
+
+        match (history.recent().back(), transaction.amount()) {
+            (Some(previous), Some(amount))
+                if previous.transaction_type() == TransactionType::Deposit
+                    && matches!(previous.amount(), Some(deposit) if amount >= deposit) =>
+            {
+                Some(format!("withdrew deposit {} in full", previous.tx()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Raise an alert when more than `max_disputes` disputes land within the last `window` transactions.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeBurst {
+    pub max_disputes: usize,
+    pub window: usize,
+}
+
+impl Rule for DisputeBurst {
+    fn name(&self) -> &'static str {
+        "dispute_burst"
+    }
+
+    /// The transaction being evaluated makes up the rest of the window
+    fn lookback(&self) -> usize {
+        self.window.saturating_sub(1)
+    }
+
+    fn evaluate(
+        &self,
+        transaction: &TransactionInput,
+        _state: &TransactionRunningState,
+        history: &History,
+    ) -> Option<String> {
+        if transaction.transaction_type() != TransactionType::Dispute || self.window == 0 {
+            return None;
+        }
+
+        let recent = history.recent();
+        let window_start = recent.len().saturating_sub(self.window - 1);
+        let disputes = recent
+            .iter()
+            .skip(window_start)
+            .filter(|previous| previous.transaction_type() == TransactionType::Dispute)
+            .count()
+            + 1;
+        if disputes > self.max_disputes {
+            Some(format!(
+                "{} disputes within {} transactions",
+                disputes, self.window
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ConfiguredRule {
+    rule: Box<dyn Rule>,
+    auto_lock: bool,
+}
+
+/// The set of rules evaluated against every transaction.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<ConfiguredRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig<T> {
+    #[serde(default)]
+    auto_lock: bool,
+    #[serde(flatten)]
+    rule: T,
+}
+
+/// Built-in rules configuration, a rule is only enabled when its section is present.
+///
+/// ```toml
+/// [dispute_ratio]
+/// max_ratio = 0.5
+/// min_transactions = 4
+/// auto_lock = true
+///
+/// [deposit_withdrawal]
+///
+/// [dispute_burst]
+/// max_disputes = 3
+/// window = 10
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesConfig {
+    dispute_ratio: Option<RuleConfig<DisputeRatio>>,
+    deposit_withdrawal: Option<RuleConfig<DepositWithdrawal>>,
+    dispute_burst: Option<RuleConfig<DisputeBurst>>,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Rules { rules: Vec::new() }
+    }
+
+    pub fn from_toml(input: &str) -> Result<Self, Error> {
+        let config: RulesConfig = toml::from_str(input)?;
+        let mut rules = Rules::new();
+
+        if let Some(config) = config.dispute_ratio {
+            if !config.rule.max_ratio.is_finite() || config.rule.max_ratio < 0.0 {
+                return Err("dispute_ratio max_ratio must be a positive number".into());
+            }
+            rules = rules.with_rule(config.rule, config.auto_lock);
+        }
+        if let Some(config) = config.deposit_withdrawal {
+            rules = rules.with_rule(config.rule, config.auto_lock);
+        }
+        if let Some(config) = config.dispute_burst {
+            if config.rule.window == 0 {
+                return Err("dispute_burst window must be greater than zero".into());
+            }
+            rules = rules.with_rule(config.rule, config.auto_lock);
+        }
+
+        Ok(rules)
+    }
+
+    /// Add a rule, when `auto_lock` is set an alert from the rule locks the account
+    pub fn with_rule<R: Rule + 'static>(mut self, rule: R, auto_lock: bool) -> Self {
+        self.rules.push(ConfiguredRule {
+            rule: Box::new(rule),
+            auto_lock,
+        });
+        self
+    }
+
+    /// Number of the most recent applied transactions any rule looks back over
+    pub fn lookback(&self) -> usize {
+        self.rules
+            .iter()
+            .map(|configured_rule| configured_rule.rule.lookback())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn evaluate(
+        &self,
+        transaction: &TransactionInput,
+        state: &mut TransactionRunningState,
+        history: &History,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+
+        for configured_rule in &self.rules {
+            if let Some(detail) = configured_rule.rule.evaluate(transaction, state, history) {
+                if configured_rule.auto_lock {
+                    state.lock();
+                }
+                alerts.push(Alert {
+                    client: transaction.client(),
+                    tx: transaction.tx(),
+                    rule: configured_rule.rule.name(),
+                    detail,
+                    locked: configured_rule.auto_lock,
+                });
+            }
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_enabled_rules() {
+        let rules = Rules::from_toml(
+            r#"
+            [deposit_withdrawal]
+            auto_lock = true
+
+            [dispute_burst]
+            max_disputes = 2
+            window = 5
+            "#,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = rules.rules.iter().map(|r| r.rule.name()).collect();
+        assert_eq!(names, vec!["deposit_withdrawal", "dispute_burst"]);
+        assert!(rules.rules[0].auto_lock);
+        assert!(!rules.rules[1].auto_lock);
+    }
+
+    #[test]
+    fn reject_empty_dispute_burst_window() {
+        let rules = Rules::from_toml(
+            r#"
+            [dispute_burst]
+            max_disputes = 2
+            window = 0
+            "#,
+        );
+
+        assert!(rules.is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use super::{
+    Account, DisputeStatus, Moment, Settled, Timestamp, TransactionInput, TransactionType,
+};
+use crate::Error;
+use bincode::Options;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Version of the snapshot schema this build writes
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+/// Oldest version able to read the snapshots this build writes
+const READABLE_FROM: u16 = 1;
+
+/// Marks the start of a snapshot file
+const MAGIC: &[u8; 8] = b"TXSNAP\0\0";
+
+/// Largest snapshot, in bytes, written or read. A corrupt length in a snapshot is refused
+/// rather than trusted to size an allocation.
+const MAX_SNAPSHOT_SIZE: u64 = 1 << 30;
+
+const ENGINE_SECTION: &str = "engine";
+const ACCOUNTS_SECTION: &str = "accounts";
+const ADJUSTMENTS_SECTION: &str = "adjustments";
+
+/// The state of every account, saved so a later run can carry on from it rather than replay
+/// the transactions that led to it.
+///
+/// A snapshot is the magic bytes followed by the schema version it was written with, the
+/// oldest version able to read it and a list of named sections, each encoded on its own.
+/// Readers skip the sections they do not know, so state added in a later version goes in a
+/// new section and older readers still load the rest. A change older readers cannot ignore
+/// raises the version able to read the snapshot, and older readers refuse it rather than
+/// misread it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Snapshot {
+    /// Latest timestamp of any transaction applied
+    pub latest: Option<Timestamp>,
+    /// In client order
+    pub accounts: Vec<AccountSnapshot>,
+    /// Ids of the admin adjustments applied when the policy rejects duplicates, in order
+    pub adjustment_ids: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    version: u16,
+    readable_from: u16,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Section {
+    name: String,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EngineSection {
+    latest: Option<Time>,
+}
+
+/// A single client's account along with the state the engine needs to carry on with it. The
+/// ledger is kept as its balances and the rules as their counts and the few transactions they
+/// look back over, the postings and the as-of balances before the snapshot are not carried over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct AccountSnapshot {
+    pub client: u16,
+    pub locked: bool,
+    pub balances: Vec<(Account, f64)>,
+    /// Amounts in the rolling limit window, oldest first
+    pub window: Vec<f64>,
+    /// Sum of the amounts in the rolling limit window
+    pub volume: f64,
+    /// Settled transactions with their dispute state, in transaction id order
+    pub settled: Vec<SettledSnapshot>,
+    /// Applied transactions of each type, in the order the types are declared
+    pub counts: [usize; 5],
+    /// The most recent applied transactions the rules look back over, oldest first
+    pub recent: Vec<TransactionSnapshot>,
+    /// Rows received for the client, applied or not
+    pub rows: u64,
+    /// Timestamp of the client's latest row, the rows after it are checked against
+    pub last_timestamp: Option<Time>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct SettledSnapshot {
+    tx: u32,
+    transaction_type: TransactionType,
+    amount: f64,
+    timestamp: Option<Time>,
+    dispute: Option<DisputeStatus>,
+    /// Row and timestamp the dispute was opened at
+    opened: Option<(u64, Option<Time>)>,
+    disputed: f64,
+    held: f64,
+    charged_back: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct TransactionSnapshot {
+    transaction_type: TransactionType,
+    tx: u32,
+    amount: Option<f64>,
+    timestamp: Option<Time>,
+}
+
+/// A timestamp as whole seconds since the Unix epoch and the nanoseconds past them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(super) struct Time(i64, u32);
+
+impl Snapshot {
+    pub fn write<W: io::Write>(&self, mut output: W) -> Result<(), Error> {
+        let engine = EngineSection {
+            latest: self.latest.map(Time::from),
+        };
+        let envelope = Envelope {
+            version: SNAPSHOT_VERSION,
+            readable_from: READABLE_FROM,
+            sections: vec![
+                Section::new(ENGINE_SECTION, &engine)?,
+                Section::new(ACCOUNTS_SECTION, &self.accounts)?,
+                Section::new(ADJUSTMENTS_SECTION, &self.adjustment_ids)?,
+            ],
+        };
+
+        output.write_all(MAGIC)?;
+        options().serialize_into(&mut output, &envelope)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    pub fn read<R: io::Read>(mut input: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        input
+            .read_exact(&mut magic)
+            .ok()
+            .filter(|()| &magic == MAGIC)
+            .ok_or("not a snapshot file")?;
+        let envelope: Envelope = options().deserialize_from(input)?;
+        if envelope.readable_from > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} needs version {} or later to read, this is version {}",
+                envelope.version, envelope.readable_from, SNAPSHOT_VERSION
+            )
+            .into());
+        }
+
+        let mut snapshot = Snapshot::default();
+        for section in envelope.sections {
+            match section.name.as_str() {
+                ENGINE_SECTION => {
+                    let engine: EngineSection = section.decode()?;
+                    snapshot.latest = engine.latest.map(Time::to_timestamp).transpose()?;
+                }
+                ACCOUNTS_SECTION => snapshot.accounts = section.decode()?,
+                ADJUSTMENTS_SECTION => snapshot.adjustment_ids = section.decode()?,
+                _ => (),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+impl Section {
+    fn new<T: Serialize>(name: &str, value: &T) -> Result<Self, Error> {
+        Ok(Section {
+            name: name.to_string(),
+            data: options().serialize(value)?,
+        })
+    }
+
+    fn decode<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        options()
+            .deserialize(&self.data)
+            .map_err(|err| format!("invalid {} section: {}", self.name, err).into())
+    }
+}
+
+impl SettledSnapshot {
+    pub fn new(tx: u32, settled: &Settled) -> Self {
+        SettledSnapshot {
+            tx,
+            transaction_type: settled.transaction_type,
+            amount: settled.amount,
+            timestamp: settled.timestamp.map(Time::from),
+            dispute: settled.dispute,
+            opened: settled
+                .opened
+                .map(|opened| (opened.row as u64, opened.timestamp.map(Time::from))),
+            disputed: settled.disputed,
+            held: settled.held,
+            charged_back: settled.charged_back,
+        }
+    }
+
+    pub fn to_settled(&self) -> Result<(u32, Settled), Error> {
+        let opened = match self.opened {
+            Some((row, timestamp)) => Some(Moment {
+                row: row as usize,
+                timestamp: timestamp.map(Time::to_timestamp).transpose()?,
+            }),
+            None => None,
+        };
+        let settled = Settled {
+            transaction_type: self.transaction_type,
+            amount: self.amount,
+            timestamp: self.timestamp.map(Time::to_timestamp).transpose()?,
+            dispute: self.dispute,
+            opened,
+            disputed: self.disputed,
+            held: self.held,
+            charged_back: self.charged_back,
+        };
+        Ok((self.tx, settled))
+    }
+}
+
+impl TransactionSnapshot {
+    pub fn new(transaction: &TransactionInput) -> Self {
+        TransactionSnapshot {
+            transaction_type: transaction.transaction_type(),
+            tx: transaction.tx(),
+            amount: transaction.amount(),
+            timestamp: transaction.timestamp().map(Time::from),
+        }
+    }
+
+    pub fn to_transaction(&self, client: u16) -> Result<TransactionInput, Error> {
+        let transaction =
+            TransactionInput::new(self.transaction_type, client, self.tx, self.amount);
+        Ok(match self.timestamp {
+            Some(timestamp) => transaction.with_timestamp(timestamp.to_timestamp()?),
+            None => transaction,
+        })
+    }
+}
+
+impl Time {
+    pub fn to_timestamp(self) -> Result<Timestamp, Error> {
+        Utc.timestamp_opt(self.0, self.1)
+            .single()
+            .ok_or_else(|| "invalid timestamp in snapshot".into())
+    }
+}
+
+impl From<Timestamp> for Time {
+    fn from(timestamp: Timestamp) -> Self {
+        Time(timestamp.timestamp(), timestamp.timestamp_subsec_nanos())
+    }
+}
+
+/// Integers are variable length encoded to keep snapshots small
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_SNAPSHOT_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_engine::{parse_timestamp, Balance, Engine, Outcome, Rejection};
+
+    fn save(engine: &Engine) -> Vec<u8> {
+        let mut output = Vec::new();
+        engine.save_snapshot(&mut output).unwrap();
+        output
+    }
+
+    fn balances(engine: &Engine) -> Vec<Balance> {
+        engine.accounts().map(Balance::from_account).collect()
+    }
+
+    fn write_envelope(envelope: &Envelope) -> Vec<u8> {
+        let mut output = MAGIC.to_vec();
+        options().serialize_into(&mut output, envelope).unwrap();
+        output
+    }
+
+    #[test]
+    fn carry_on_from_a_snapshot() {
+        let timestamp = parse_timestamp("2024-01-01T09:30:00.25Z").unwrap();
+        let mut engine = Engine::new();
+        engine
+            .apply(TransactionInput::deposit(1, 1, 10.0).with_timestamp(timestamp))
+            .unwrap();
+        engine.apply(TransactionInput::deposit(1, 2, 5.0)).unwrap();
+        engine.apply(TransactionInput::dispute(1, 1)).unwrap();
+        engine.apply(TransactionInput::deposit(2, 3, 7.5)).unwrap();
+        engine
+            .apply(TransactionInput::withdrawal(2, 4, 1.5))
+            .unwrap();
+        engine
+            .apply(TransactionInput::chargeback(2, 3))
+            .unwrap_err();
+        let snapshot = save(&engine);
+
+        let mut restored = Engine::new();
+        restored.load_snapshot(snapshot.as_slice()).unwrap();
+
+        assert_eq!(balances(&restored), balances(&engine));
+        assert_eq!(restored.open_disputes(), engine.open_disputes());
+        assert!(restored.balances_as_of(timestamp).is_empty());
+        assert_eq!(save(&restored), snapshot);
+        assert!(restored.trial_balance().is_balanced());
+
+        let rest = [
+            TransactionInput::chargeback(1, 1),
+            TransactionInput::dispute(1, 1),
+            TransactionInput::deposit(1, 5, 1.0),
+            TransactionInput::dispute(2, 4),
+        ];
+        for transaction in rest.iter() {
+            let outcome = |result: Result<Outcome, Rejection>| result.map(|outcome| outcome.amount);
+            assert_eq!(
+                outcome(restored.apply(transaction.clone())),
+                outcome(engine.apply(transaction.clone()))
+            );
+        }
+        assert_eq!(balances(&restored), balances(&engine));
+        assert!(restored.load_snapshot(snapshot.as_slice()).is_err());
+    }
+
+    #[test]
+    fn skip_unknown_sections_and_refuse_newer_schemas() {
+        let mut engine = Engine::new();
+        engine.apply(TransactionInput::deposit(1, 1, 10.0)).unwrap();
+        let accounts = Snapshot::read(save(&engine).as_slice()).unwrap().accounts;
+        let mut envelope = Envelope {
+            version: SNAPSHOT_VERSION + 1,
+            readable_from: SNAPSHOT_VERSION,
+            sections: vec![
+                Section::new("statements", &vec![1u32, 2, 3]).unwrap(),
+                Section::new(ACCOUNTS_SECTION, &accounts).unwrap(),
+            ],
+        };
+
+        let snapshot = Snapshot::read(write_envelope(&envelope).as_slice()).unwrap();
+        assert_eq!(snapshot.accounts, accounts);
+
+        envelope.readable_from = SNAPSHOT_VERSION + 1;
+        let err = Snapshot::read(write_envelope(&envelope).as_slice()).unwrap_err();
+        assert!(err.to_string().contains("needs version 2 or later"));
+
+        assert!(Snapshot::read("type,client,tx,amount\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn refuse_a_corrupt_length() {
+        let mut snapshot = MAGIC.to_vec();
+        // Version 1 with a single section whose name claims to be a terabyte long
+        snapshot.extend_from_slice(&[1, 1, 1, 253]);
+        snapshot.extend_from_slice(&(1u64 << 40).to_le_bytes());
+
+        let err = Snapshot::read(snapshot.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("size limit"));
+    }
+}
@@ -1,6 +1,11 @@
-use serde::Deserialize;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// When a transaction happened, read as RFC 3339 or as whole seconds since the Unix epoch
+pub type Timestamp = DateTime<Utc>;
+
+/// Snapshots store types by their position here, so new types go at the end.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -10,18 +15,159 @@ pub enum TransactionType {
     Chargeback,
 }
 
-#[derive(Debug, Deserialize)]
+impl TransactionType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct TransactionInput {
     #[serde(rename = "type")]
-    pub transaction_type: TransactionType,
+    transaction_type: TransactionType,
 
     #[serde(rename = "client")]
-    pub client: u16,
+    client: u16,
 
     #[serde(rename = "tx")]
-    pub tx: u32,
+    tx: u32,
 
     #[serde(deserialize_with = "csv::invalid_option")]
     #[serde(rename = "amount")]
-    pub amount: Option<f64>,
+    amount: Option<f64>,
+
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    #[serde(rename = "timestamp")]
+    timestamp: Option<Timestamp>,
+}
+
+impl TransactionInput {
+    pub fn new(
+        transaction_type: TransactionType,
+        client: u16,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> Self {
+        TransactionInput {
+            transaction_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn deposit(client: u16, tx: u32, amount: f64) -> Self {
+        TransactionInput::new(TransactionType::Deposit, client, tx, Some(amount))
+    }
+
+    pub fn withdrawal(client: u16, tx: u32, amount: f64) -> Self {
+        TransactionInput::new(TransactionType::Withdrawal, client, tx, Some(amount))
+    }
+
+    /// Dispute the client's earlier deposit or withdrawal `tx`
+    pub fn dispute(client: u16, tx: u32) -> Self {
+        TransactionInput::new(TransactionType::Dispute, client, tx, None)
+    }
+
+    pub fn resolve(client: u16, tx: u32) -> Self {
+        TransactionInput::new(TransactionType::Resolve, client, tx, None)
+    }
+
+    pub fn chargeback(client: u16, tx: u32) -> Self {
+        TransactionInput::new(TransactionType::Chargeback, client, tx, None)
+    }
+
+    pub fn transaction_type(&self) -> TransactionType {
+        self.transaction_type
+    }
+
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    pub fn tx(&self) -> u32 {
+        self.tx
+    }
+
+    pub fn amount(&self) -> Option<f64> {
+        self.amount
+    }
+
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+}
+
+/// Parse a timestamp as whole seconds since the Unix epoch when it is all digits, otherwise
+/// as RFC 3339, an empty field has no timestamp
+pub fn parse_timestamp(input: &str) -> Result<Timestamp, String> {
+    if !input.is_empty() && input.bytes().all(|byte| byte.is_ascii_digit()) {
+        input
+            .parse()
+            .ok()
+            .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+            .ok_or_else(|| format!("timestamp {} is out of range", input))
+    } else {
+        DateTime::parse_from_rfc3339(input)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .map_err(|err| format!("invalid timestamp {}: {}", input, err))
+    }
+}
+
+pub(super) fn deserialize_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Timestamp>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(input) if !input.is_empty() => parse_timestamp(&input)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &str) -> Result<Vec<TransactionInput>, csv::Error> {
+        crate::csv_reader_builder()
+            .from_reader(input.as_bytes())
+            .deserialize()
+            .collect()
+    }
+
+    #[test]
+    fn timestamp_column_is_optional() {
+        let rows = read(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,1.0,2024-03-01T12:00:00Z\n\
+             deposit,1,2,1.0,1709294400\n\
+             deposit,1,3,1.0,\n\
+             dispute,1,1\n",
+        )
+        .unwrap();
+        let expected = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(rows[0].timestamp(), Some(expected));
+        assert_eq!(rows[1].timestamp(), Some(expected));
+        assert_eq!(rows[2].timestamp(), None);
+        assert_eq!(rows[3].timestamp(), None);
+        assert_eq!(
+            read("type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap()[0].timestamp(),
+            None
+        );
+        assert!(read("type,client,tx,amount,timestamp\ndeposit,1,1,1.0,yesterday\n").is_err());
+    }
 }
@@ -1,81 +1,206 @@
-use serde::{Serialize, Serializer};
+use super::ledger::agrees;
+use super::{Account, Ledger, Precision, Rejection};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 
-#[derive(Debug, Serialize)]
+/// Client balances, derived from the client's ledger after every posting.
+#[derive(Debug, Clone, PartialEq)]
 pub struct TransactionRunningState {
-    pub client: u16,
-    #[serde(serialize_with = "round_serialize")]
-    pub available: f64,
-    #[serde(serialize_with = "round_serialize")]
-    pub held: f64,
-    #[serde(serialize_with = "round_serialize")]
-    pub total: f64,
-    pub locked: bool,
+    client: u16,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: bool,
+    ledger: Ledger,
+    /// Rounding applied to the balances when they are written out
+    precision: Precision,
 }
 
-fn round_serialize<S: Serializer>(value: &f64, s: S) -> Result<S::Ok, S::Error> {
-    // Round to a maximum of 4 decimal places
-    s.serialize_f64((value * 10000.0).round() / 10000.0)
+impl Serialize for TransactionRunningState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("TransactionRunningState", 5)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("available", &self.precision.round(self.available))?;
+        state.serialize_field("held", &self.precision.round(self.held))?;
+        state.serialize_field("total", &self.precision.round(self.total))?;
+        state.serialize_field("locked", &self.locked)?;
+        state.end()
+    }
 }
 
 impl TransactionRunningState {
-    pub fn new(client: u16) -> Self {
+    pub(crate) fn new(client: u16) -> Self {
         Self {
             client,
             available: 0.0,
             held: 0.0,
             total: 0.0,
             locked: false,
+            ledger: Ledger::new(client),
+            precision: Precision::default(),
         }
     }
 
-    pub fn deposit(&mut self, amount: f64) {
+    pub(crate) fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+    }
+
+    /// Pick up the balances and lock from a snapshot of the account
+    pub(crate) fn restore(&mut self, balances: &[(Account, f64)], locked: bool) {
+        self.ledger.restore(balances);
+        self.locked = locked;
+        self.available = self.ledger.balance(Account::ClientAvailable);
+        self.held = self.ledger.balance(Account::ClientHeld);
+        self.total = self.available + self.held;
+    }
+
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    pub fn available(&self) -> f64 {
+        self.available
+    }
+
+    pub fn held(&self) -> f64 {
+        self.held
+    }
+
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// The balances written out agree with the client accounts of the ledger
+    pub fn matches_ledger(&self) -> bool {
+        let available = self.ledger.balance(Account::ClientAvailable);
+        let held = self.ledger.balance(Account::ClientHeld);
+        agrees(self.available, available)
+            && agrees(self.held, held)
+            && agrees(self.total, available + held)
+    }
+
+    pub(crate) fn deposit(&mut self, tx: u32, amount: f64) -> Result<(), Rejection> {
         let new_total = self.total + amount;
 
-        if new_total > self.total {
-            self.total = new_total;
-            self.calculate_available();
+        if amount.is_finite() && new_total > self.total && new_total.is_finite() {
+            self.post(tx, Account::HouseCash, Account::ClientAvailable, amount);
+            Ok(())
         } else {
-            eprintln!("Zero or Infinite amount detected, ignoring deposit");
+            // Zero, negative, Infinite or NaN amount, or one that overflows the total
+            Err(Rejection::InvalidAmount)
         }
     }
 
-    pub fn withdraw(&mut self, amount: f64) {
-        if self.available >= amount {
-            self.total -= amount;
-            self.calculate_available();
+    /// Withdraw from the available funds, an overdraft may leave them negative
+    pub(crate) fn withdraw(
+        &mut self,
+        tx: u32,
+        amount: f64,
+        allow_overdraft: bool,
+    ) -> Result<(), Rejection> {
+        if !(amount > 0.0 && amount.is_finite()) {
+            // Zero, negative, Infinite or NaN amount
+            return Err(Rejection::InvalidAmount);
+        }
+        if allow_overdraft || self.available >= amount {
+            self.post(tx, Account::ClientAvailable, Account::HouseCash, amount);
+            Ok(())
         } else {
-            eprintln!("Withdrawal amount ignored as there is not enough available balance");
+            Err(Rejection::InsufficientFunds)
+        }
+    }
+
+    /// The deposited funds are returned to the payer, the house absorbs any part of the amount
+    /// the client had spent before it could be held
+    pub(crate) fn chargeback_deposit(&mut self, tx: u32, held: f64, amount: f64) {
+        if held > 0.0 {
+            self.post(tx, Account::ClientHeld, Account::HouseCash, held);
         }
+        if amount > held {
+            self.post(
+                tx,
+                Account::ChargebackLoss,
+                Account::HouseCash,
+                amount - held,
+            );
+        }
+    }
+
+    /// The withdrawn funds are refunded to the client at the house's expense
+    pub(crate) fn chargeback_withdrawal(&mut self, tx: u32, amount: f64) {
+        self.post(
+            tx,
+            Account::ChargebackLoss,
+            Account::ClientAvailable,
+            amount,
+        );
+    }
+
+    /// The disputed withdrawal is credited to the held funds until the dispute is settled
+    pub(crate) fn credit_provisionally(&mut self, tx: u32, amount: f64) {
+        self.post(tx, Account::DisputeCredit, Account::ClientHeld, amount);
     }
 
-    // TODO Refactor Chargeback should be one function
-    pub fn chargeback_deposit(&mut self, amount: f64) {
-        self.total -= amount;
-        self.chargeback(amount);
+    /// The dispute failed, the withdrawal stands and the provisional credit is taken back
+    pub(crate) fn reverse_provisional_credit(&mut self, tx: u32, amount: f64) {
+        self.post(tx, Account::ClientHeld, Account::DisputeCredit, amount);
     }
 
-    pub fn chargeback_withdrawal(&mut self, amount: f64) {
-        self.total += amount;
-        self.chargeback(amount);
+    /// The provisional credit is released to the client and written off as a chargeback loss
+    pub(crate) fn chargeback_provisional_credit(&mut self, tx: u32, amount: f64) {
+        self.post(tx, Account::ClientHeld, Account::ClientAvailable, amount);
+        self.post(tx, Account::ChargebackLoss, Account::DisputeCredit, amount);
     }
 
-    pub fn hold(&mut self, amount: f64) {
-        self.held += amount;
-        self.calculate_available();
+    pub(crate) fn hold(&mut self, tx: u32, amount: f64) {
+        self.post(tx, Account::ClientAvailable, Account::ClientHeld, amount);
     }
 
-    pub fn release(&mut self, amount: f64) {
-        self.held -= amount;
-        self.calculate_available();
+    pub(crate) fn release(&mut self, tx: u32, amount: f64) {
+        self.post(tx, Account::ClientHeld, Account::ClientAvailable, amount);
     }
 
-    fn chargeback(&mut self, amount: f64) {
-        self.held -= amount;
+    pub(crate) fn clear_postings(&mut self) {
+        self.ledger.clear_postings();
+    }
+
+    pub(crate) fn lock(&mut self) {
         self.locked = true;
-        self.calculate_available();
     }
 
-    fn calculate_available(&mut self) {
-        self.available = self.total - self.held
+    pub(crate) fn unlock(&mut self) {
+        self.locked = false;
+    }
+
+    /// Manual correction of the balance, a negative amount debits the account
+    pub(crate) fn adjust(&mut self, tx: u32, amount: f64) -> Result<(), Rejection> {
+        if amount == 0.0 || !(self.total + amount).is_finite() {
+            return Err(Rejection::InvalidAmount);
+        }
+
+        if amount > 0.0 {
+            self.post(tx, Account::Adjustments, Account::ClientAvailable, amount);
+        } else {
+            self.post(tx, Account::ClientAvailable, Account::Adjustments, -amount);
+        }
+        Ok(())
+    }
+
+    fn post(&mut self, tx: u32, debit: Account, credit: Account, amount: f64) {
+        self.ledger.post(tx, debit, credit, amount);
+        self.available = self.ledger.balance(Account::ClientAvailable);
+        self.held = self.ledger.balance(Account::ClientHeld);
+        self.total = self.available + self.held;
     }
 }
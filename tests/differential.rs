@@ -0,0 +1,81 @@
+//! Differential test running random transaction streams through both the engine and the
+//! reference model in `tests/reference`, proptest shrinks any disagreement to a minimal input.
+
+mod reference;
+
+use proptest::prelude::*;
+use reference::Row;
+use std::collections::BTreeMap;
+use toy_transaction::transaction_engine::{Engine, TransactionInput, TransactionType};
+
+const UNITS: f64 = 10_000.0;
+
+fn row() -> impl Strategy<Value = Row> {
+    let client = 1u16..=3;
+    let tx = 1u32..=30;
+    let amount = prop_oneof![
+        1 => Just(None),
+        9 => (-100i64..20_000).prop_map(|cents| Some(cents * 100)),
+    ];
+    // Disputes and their settlements are mostly for the full amount
+    let part = prop_oneof![
+        3 => Just(None),
+        1 => (-100i64..20_000).prop_map(|cents| Some(cents * 100)),
+    ];
+    prop_oneof![
+        4 => (client.clone(), tx.clone(), amount.clone())
+            .prop_map(|(client, tx, amount)| Row::Deposit { client, tx, amount }),
+        3 => (client.clone(), tx.clone(), amount)
+            .prop_map(|(client, tx, amount)| Row::Withdrawal { client, tx, amount }),
+        2 => (client.clone(), tx.clone(), part.clone())
+            .prop_map(|(client, tx, amount)| Row::Dispute { client, tx, amount }),
+        1 => (client.clone(), tx.clone(), part.clone())
+            .prop_map(|(client, tx, amount)| Row::Resolve { client, tx, amount }),
+        1 => (client, tx, part)
+            .prop_map(|(client, tx, amount)| Row::Chargeback { client, tx, amount }),
+    ]
+}
+
+fn transaction_input(row: &Row) -> TransactionInput {
+    let (transaction_type, client, tx, amount) = match *row {
+        Row::Deposit { client, tx, amount } => (TransactionType::Deposit, client, tx, amount),
+        Row::Withdrawal { client, tx, amount } => (TransactionType::Withdrawal, client, tx, amount),
+        Row::Dispute { client, tx, amount } => (TransactionType::Dispute, client, tx, amount),
+        Row::Resolve { client, tx, amount } => (TransactionType::Resolve, client, tx, amount),
+        Row::Chargeback { client, tx, amount } => (TransactionType::Chargeback, client, tx, amount),
+    };
+    let amount = amount.map(|amount| amount as f64 / UNITS);
+    TransactionInput::new(transaction_type, client, tx, amount)
+}
+
+fn units(amount: f64) -> i64 {
+    (amount * UNITS).round() as i64
+}
+
+fn run_engine(rows: &[Row]) -> BTreeMap<u16, reference::Account> {
+    let mut engine = Engine::new();
+    for row in rows {
+        let _ = engine.apply(transaction_input(row));
+    }
+
+    engine
+        .accounts()
+        .map(|account| {
+            let state = reference::Account {
+                available: units(account.available()),
+                held: units(account.held()),
+                locked: account.locked(),
+            };
+            (account.client(), state)
+        })
+        .collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn engine_matches_reference_model(rows in prop::collection::vec(row(), 0..120)) {
+        prop_assert_eq!(run_engine(&rows), reference::run(&rows));
+    }
+}
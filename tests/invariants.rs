@@ -0,0 +1,186 @@
+//! Property tests asserting the account invariants hold after every transaction of a
+//! randomly generated sequence.
+
+use proptest::prelude::*;
+use std::rc::Rc;
+use toy_transaction::transaction_engine::{
+    Account, JournalEntry, JournalType, Policy, TransactionInput, TransactionProcessor,
+    TransactionRunningState, TransactionType,
+};
+
+const CLIENT: u16 = 1;
+/// Allowance for floating point error accumulated over a sequence
+const EPSILON: f64 = 1e-6;
+
+fn transaction_type() -> impl Strategy<Value = TransactionType> {
+    prop_oneof![
+        3 => Just(TransactionType::Deposit),
+        2 => Just(TransactionType::Withdrawal),
+        1 => Just(TransactionType::Dispute),
+        1 => Just(TransactionType::Resolve),
+        1 => Just(TransactionType::Chargeback),
+    ]
+}
+
+fn amount() -> impl Strategy<Value = Option<f64>> {
+    prop_oneof![
+        1 => Just(None),
+        1 => (-1_000i64..0).prop_map(|cents| Some(cents as f64 / 100.0)),
+        8 => (0i64..100_000).prop_map(|cents| Some(cents as f64 / 100.0)),
+    ]
+}
+
+/// Deposits and withdrawals take the next transaction id, disputes and their settlements
+/// refer to any id seen so far or one that has not been used yet, for a part of the amount
+/// or all of it.
+fn transactions() -> impl Strategy<Value = Vec<TransactionInput>> {
+    prop::collection::vec(
+        (transaction_type(), amount(), any::<prop::sample::Index>()),
+        1..80,
+    )
+    .prop_map(|rows| {
+        let mut next_tx = 1;
+        rows.into_iter()
+            .map(|(transaction_type, amount, index)| match transaction_type {
+                TransactionType::Deposit | TransactionType::Withdrawal => {
+                    next_tx += 1;
+                    TransactionInput::new(transaction_type, CLIENT, next_tx - 1, amount)
+                }
+                _ => {
+                    let tx = index.index(next_tx as usize + 1) as u32;
+                    TransactionInput::new(transaction_type, CLIENT, tx, amount)
+                }
+            })
+            .collect()
+    })
+}
+
+/// Apply the transactions in order under the default policy, returning a journal entry for
+/// each along with the final account.
+fn process(transactions: &[TransactionInput]) -> (Vec<JournalEntry>, TransactionRunningState) {
+    process_with(TransactionProcessor::new(CLIENT), transactions)
+}
+
+fn process_with(
+    mut processor: TransactionProcessor,
+    transactions: &[TransactionInput],
+) -> (Vec<JournalEntry>, TransactionRunningState) {
+    let journal = transactions
+        .iter()
+        .map(|transaction| match processor.apply(transaction.clone()) {
+            Ok(outcome) => outcome.journal,
+            Err(rejection) => JournalEntry::new(
+                JournalType::Transaction(transaction.transaction_type()),
+                transaction.tx(),
+                transaction.amount(),
+                &Err(rejection),
+                processor.running_state(),
+            ),
+        })
+        .collect();
+    (journal, processor.into_running_state())
+}
+
+fn is_movement(entry: &JournalEntry) -> bool {
+    matches!(
+        entry.journal_type,
+        JournalType::Transaction(TransactionType::Deposit)
+            | JournalType::Transaction(TransactionType::Withdrawal)
+    )
+}
+
+proptest! {
+    #[test]
+    fn total_is_available_plus_held_and_matches_the_books(transactions in transactions()) {
+        let mut processor = TransactionProcessor::new(CLIENT);
+        for transaction in transactions {
+            let tx = transaction.tx();
+            let _ = processor.apply(transaction);
+            let state = processor.running_state();
+            prop_assert!(
+                (state.total() - (state.available() + state.held())).abs() <= EPSILON,
+                "total {} but available {} and held {} after tx {}",
+                state.total(),
+                state.available(),
+                state.held(),
+                tx
+            );
+            let ledger = state.ledger();
+            // What the client holds is owed by the house, so is matched by the debit accounts
+            let house = ledger.balance(Account::HouseCash)
+                + ledger.balance(Account::ChargebackLoss)
+                + ledger.balance(Account::DisputeCredit)
+                + ledger.balance(Account::Adjustments);
+            prop_assert!(
+                (state.total() - house).abs() <= EPSILON,
+                "total {} but the house owes {} after tx {}",
+                state.total(),
+                house,
+                tx
+            );
+            prop_assert!(ledger.trial_balance().is_balanced());
+        }
+    }
+
+    #[test]
+    fn held_is_never_negative(transactions in transactions()) {
+        for entry in process(&transactions).0 {
+            prop_assert!(entry.held >= -EPSILON, "held {} after tx {}", entry.held, entry.tx);
+        }
+    }
+
+    #[test]
+    fn money_is_only_created_by_deposits(transactions in transactions()) {
+        let mut deposited = 0.0;
+        for entry in process(&transactions).0 {
+            if entry.journal_type == JournalType::Transaction(TransactionType::Deposit)
+                && entry.rejection.is_none()
+            {
+                deposited += entry.amount.unwrap();
+            }
+            prop_assert!(
+                entry.total <= deposited + EPSILON,
+                "total {} exceeds deposits {} after tx {}",
+                entry.total,
+                deposited,
+                entry.tx
+            );
+        }
+    }
+
+    #[test]
+    fn locked_accounts_stay_locked(transactions in transactions()) {
+        // Nothing but an admin unlock can clear the lock, by default a locked account still
+        // takes deposits and withdrawals
+        let journal = process(&transactions).0;
+        for pair in journal.windows(2) {
+            prop_assert!(!pair[0].locked || pair[1].locked);
+        }
+    }
+
+    #[test]
+    fn locked_accounts_reject_deposits_and_withdrawals_when_the_policy_says(
+        transactions in transactions()
+    ) {
+        let policy = Policy::from_toml("[locking]\nreject_when_locked = true").unwrap();
+        let processor = TransactionProcessor::new(CLIENT).with_policy(Rc::new(policy));
+        let journal = process_with(processor, &transactions).0;
+        for pair in journal.windows(2) {
+            let (previous, entry) = (&pair[0], &pair[1]);
+            if !previous.locked {
+                continue;
+            }
+            prop_assert!(entry.locked);
+            if is_movement(entry) {
+                prop_assert!(entry.rejection.is_some());
+                prop_assert_eq!(entry.available, previous.available);
+                prop_assert_eq!(entry.held, previous.held);
+            }
+        }
+    }
+
+    #[test]
+    fn replay_is_deterministic(transactions in transactions()) {
+        prop_assert_eq!(process(&transactions), process(&transactions));
+    }
+}
@@ -0,0 +1,192 @@
+//! A deliberately simple model of the transaction semantics, amounts are exact integers in
+//! ten-thousandths and every rule is spelled out in one place.
+
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Row {
+    Deposit {
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    },
+    Withdrawal {
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    },
+    Dispute {
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    },
+    Resolve {
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    },
+    Chargeback {
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    },
+}
+
+impl Row {
+    pub fn client(&self) -> u16 {
+        match *self {
+            Row::Deposit { client, .. }
+            | Row::Withdrawal { client, .. }
+            | Row::Dispute { client, .. }
+            | Row::Resolve { client, .. }
+            | Row::Chargeback { client, .. } => client,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dispute {
+    None,
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Movement {
+    amount: i64,
+    deposit: bool,
+    dispute: Dispute,
+    disputed: i64,
+    charged_back: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Account {
+    pub available: i64,
+    pub held: i64,
+    pub locked: bool,
+}
+
+#[derive(Debug, Default)]
+struct Client {
+    account: Account,
+    movements: HashMap<u32, Movement>,
+}
+
+/// Apply the rows in order and return the final account of every client that appeared.
+pub fn run(rows: &[Row]) -> BTreeMap<u16, Account> {
+    let mut clients: BTreeMap<u16, Client> = BTreeMap::new();
+
+    for row in rows {
+        let client = clients.entry(row.client()).or_default();
+        apply(client, *row);
+    }
+
+    clients
+        .into_iter()
+        .map(|(id, client)| (id, client.account))
+        .collect()
+}
+
+fn apply(client: &mut Client, row: Row) {
+    let account = &mut client.account;
+
+    match row {
+        Row::Deposit { tx, amount, .. } => {
+            let amount = match amount {
+                Some(amount) if amount > 0 => amount,
+                _ => return,
+            };
+            account.available += amount;
+            record(&mut client.movements, tx, amount, true);
+        }
+        Row::Withdrawal { tx, amount, .. } => {
+            let amount = match amount {
+                Some(amount) if amount > 0 && amount <= account.available => amount,
+                _ => return,
+            };
+            account.available -= amount;
+            record(&mut client.movements, tx, amount, false);
+        }
+        Row::Dispute { tx, amount, .. } => {
+            if let Some(movement) = client.movements.get_mut(&tx) {
+                let undisputed = movement.amount - movement.disputed - movement.charged_back;
+                let part = match part(amount, undisputed) {
+                    Some(part) => part,
+                    None => return,
+                };
+                // A disputed withdrawal is provisionally credited, the money already left
+                if movement.deposit {
+                    account.available -= part;
+                }
+                account.held += part;
+                movement.disputed += part;
+                movement.dispute = Dispute::Open;
+            }
+        }
+        Row::Resolve { tx, amount, .. } => {
+            if let Some(movement) = client.movements.get_mut(&tx) {
+                if movement.dispute != Dispute::Open {
+                    return;
+                }
+                let part = match part(amount, movement.disputed) {
+                    Some(part) => part,
+                    None => return,
+                };
+                account.held -= part;
+                if movement.deposit {
+                    account.available += part;
+                }
+                movement.disputed -= part;
+                if movement.disputed == 0 {
+                    movement.dispute = Dispute::Resolved;
+                }
+            }
+        }
+        Row::Chargeback { tx, amount, .. } => {
+            if let Some(movement) = client.movements.get_mut(&tx) {
+                if movement.dispute != Dispute::Open {
+                    return;
+                }
+                let part = match part(amount, movement.disputed) {
+                    Some(part) => part,
+                    None => return,
+                };
+                account.held -= part;
+                // A charged back withdrawal is refunded by releasing the provisional credit
+                if !movement.deposit {
+                    account.available += part;
+                }
+                account.locked = true;
+                movement.disputed -= part;
+                movement.charged_back += part;
+                if movement.disputed == 0 {
+                    movement.dispute = Dispute::ChargedBack;
+                }
+            }
+        }
+    }
+}
+
+/// The part of `remaining` a dispute, resolve or chargeback acts on, all of it when no amount
+/// is given, nothing when the amount is invalid or more than remains
+fn part(amount: Option<i64>, remaining: i64) -> Option<i64> {
+    match amount {
+        _ if remaining == 0 => None,
+        None => Some(remaining),
+        Some(amount) if amount > 0 && amount <= remaining => Some(amount),
+        Some(_) => None,
+    }
+}
+
+/// The first use of a transaction id is the one that can be disputed
+fn record(movements: &mut HashMap<u32, Movement>, tx: u32, amount: i64, deposit: bool) {
+    movements.entry(tx).or_insert(Movement {
+        amount,
+        deposit,
+        dispute: Dispute::None,
+        disputed: 0,
+        charged_back: 0,
+    });
+}